@@ -17,7 +17,10 @@ impl Vendor {
     fn parse(hex_str: &str) -> Self {
         let mut hex_str = hex_str.to_owned();
         hex_str.retain(|x| !x.is_ascii_whitespace());
-        Vendor(hex::decode(hex_str).unwrap().try_into().unwrap())
+        let bytes = hex::decode(hex_str).unwrap();
+        let mut report = [0; REPORT_SIZE];
+        report[..bytes.len()].copy_from_slice(&bytes);
+        Vendor(report)
     }
 }
 
@@ -53,14 +56,87 @@ fn format_reports(reports: &[Report]) -> String {
 unsafe extern "C" {
     #[link_name = "fixup_report"]
     unsafe fn c_fixup_report(new: *mut u8, old: *const u8, st: *mut State) -> u8;
+
+    #[link_name = "fixup_report_v1"]
+    unsafe fn c_fixup_report_v1(new: *mut u8, old: *const u8, st: *mut State) -> u8;
+
+    #[link_name = "report_enabled"]
+    unsafe fn c_report_enabled(new: *const u8, collections: u32) -> u8;
+
+    #[link_name = "apply_settings"]
+    unsafe fn c_apply_settings(new: *mut u8, old: *const u8, settings: *const Settings) -> u8;
+}
+
+/// `struct uclogic_settings`
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct Settings {
+    area_x: u32,
+    area_y: u32,
+    area_width: u32,
+    area_height: u32,
+    rotation: u32,
+    pressure_max: u16,
+    pressure_curve: [u16; 17],
+    flags: u8,
+    ring_mode: u8,
+    dial_mode: u8,
+    _padding: u8,
+    button_map: [u8; 64],
+}
+
+const SETTING_PRESSURE_CURVE: u8 = 1 << 0;
+const SETTING_BUTTON_MAP: u8 = 1 << 1;
+
+const WHEEL_REVERSED: u8 = 1;
+const WHEEL_OFF: u8 = 2;
+
+impl Settings {
+    /// Changes nothing on a tablet of this size
+    fn new(width: u32, height: u32) -> Self {
+        Self {
+            area_x: 0,
+            area_y: 0,
+            area_width: width,
+            area_height: height,
+            rotation: 0,
+            pressure_max: 8191,
+            pressure_curve: [0; 17],
+            flags: 0,
+            ring_mode: 0,
+            dial_mode: 0,
+            _padding: 0,
+            button_map: std::array::from_fn(|i| i as u8),
+        }
+    }
 }
 
+const COLLECTION_STYLUS: u32 = 1 << 0;
+const COLLECTION_PAD: u32 = 1 << 1;
+
 fn fixup_report(report: &Vendor, st: &mut State) -> Report {
     let mut result = [0; REPORT_SIZE];
     let res = unsafe { c_fixup_report(result.as_mut_ptr(), report.0.as_ptr(), st) };
     Report((res != 0).then_some(result))
 }
 
+fn fixup_report_v1(report: &Vendor, st: &mut State) -> Report {
+    let mut result = [0; REPORT_SIZE];
+    let res = unsafe { c_fixup_report_v1(result.as_mut_ptr(), report.0.as_ptr(), st) };
+    Report((res != 0).then_some(result))
+}
+
+fn filter_reports(reports: Vec<Report>, collections: u32) -> Vec<Report> {
+    reports
+        .into_iter()
+        .map(|r| {
+            Report(
+                r.0.filter(|bytes| unsafe { c_report_enabled(bytes.as_ptr(), collections) } != 0),
+            )
+        })
+        .collect()
+}
+
 fn run_reports(reports: &[&str]) -> Vec<Report> {
     let mut st = State::new();
     reports
@@ -69,6 +145,30 @@ fn run_reports(reports: &[&str]) -> Vec<Report> {
         .collect()
 }
 
+fn run_reports_settings(reports: &[&str], settings: &Settings) -> Vec<Report> {
+    let mut st = State::new();
+    reports
+        .iter()
+        .map(|&r| {
+            let vendor = Vendor::parse(r);
+            let Report(report) = fixup_report(&vendor, &mut st);
+            Report(report.and_then(|mut bytes| {
+                let res =
+                    unsafe { c_apply_settings(bytes.as_mut_ptr(), vendor.0.as_ptr(), settings) };
+                (res != 0).then_some(bytes)
+            }))
+        })
+        .collect()
+}
+
+fn run_reports_v1(reports: &[&str]) -> Vec<Report> {
+    let mut st = State::new();
+    reports
+        .iter()
+        .map(|&r| fixup_report_v1(&Vendor::parse(r), &mut st))
+        .collect()
+}
+
 #[test]
 fn test_button() {
     let reports = [
@@ -130,3 +230,133 @@ fn test_pen_pressure() {
     ];
     insta::assert_snapshot!(format_reports(&run_reports(&reports)));
 }
+
+#[test]
+fn test_v1_button() {
+    let reports = [
+        "07 e0 01 01 01 00 00 00", // Button 1 press
+        "07 e0 01 01 00 00 00 00", // Button 1 release
+        "07 e0 01 01 00 02 00 00", // Button 10 press
+        "07 e0 01 01 00 00 00 00", // Button 10 release
+    ];
+    insta::assert_snapshot!(format_reports(&run_reports_v1(&reports)));
+}
+
+#[test]
+fn test_v1_pen() {
+    let reports = [
+        "07 80 40 9c a8 61 00 00", // Pen hovering near bottom right
+        "07 81 20 4e d4 30 ff 07", // Tap max pressure near center
+        "07 82 20 4e d4 30 00 00", // Press lower button
+        "07 c0 20 4e d4 30 00 00", // Pen away
+    ];
+    insta::assert_snapshot!(format_reports(&run_reports_v1(&reports)));
+}
+
+#[test]
+fn test_collections() {
+    let reports = [
+        "08 e0 01 01 01 00 00 00 00 00 00 00", // Button 1 press
+        "08 80 a0 05 08 0a 00 00 00 00 00 00", // Pen hovering near top left
+        "08 f1 01 01 00 01 00 00 00 00 00 00", // Top wheel CW
+        "08 f0 01 01 00 01 00 00 00 00 ff fe", // Touch top
+        "08 f0 01 01 00 02 00 00 00 00 ff fe", // Touch top, moving
+    ];
+    let reports = filter_reports(run_reports(&reports), COLLECTION_STYLUS | COLLECTION_PAD);
+    insta::assert_snapshot!(format_reports(&reports));
+}
+
+/// Reports of `test_pen_movement` and `test_pen_tilt` on a 51689x34308 tablet
+const PEN_REPORTS: &[&str] = &[
+    "08 80 a0 05 08 0a 00 00 00 00 00 00", // Pen hovering near top left
+    "08 80 e9 c9 04 86 00 00 00 00 00 00", // Pen hovering near bottom right
+    "08 80 92 16 b6 0c 00 00 00 00 da 00", // Tilt left
+    "08 80 d1 07 aa 10 00 00 00 00 00 29", // Tilt up
+];
+
+#[test]
+fn test_settings_unchanged() {
+    let reports = [
+        "08 e0 01 01 00 10 00 00 00 00 00 00", // Button 13 press
+        "08 f1 01 01 00 01 00 00 00 00 00 00", // Top wheel CW
+        "08 81 e0 03 8b 0d ff 1f 00 00 00 00", // Tap max pressure
+    ];
+    let reports: Vec<_> = PEN_REPORTS.iter().chain(&reports).copied().collect();
+    let settings = Settings::new(51689, 34308);
+    assert_eq!(
+        format_reports(&run_reports_settings(&reports, &settings)),
+        format_reports(&run_reports(&reports))
+    );
+}
+
+#[test]
+fn test_settings_rotation() {
+    let mut settings = Settings::new(51689, 34308);
+    let mut out = String::new();
+    for rotation in 1..=3 {
+        settings.rotation = rotation;
+        out += &format!("# Rotation {}\n", rotation * 90);
+        out += &format_reports(&run_reports_settings(PEN_REPORTS, &settings));
+    }
+    insta::assert_snapshot!(out);
+}
+
+#[test]
+fn test_settings_area() {
+    let mut settings = Settings::new(51689, 34308);
+    // The middle quarter, the first report is left of it
+    settings.area_x = 12922;
+    settings.area_y = 8577;
+    settings.area_width = 25845;
+    settings.area_height = 17154;
+    insta::assert_snapshot!(format_reports(&run_reports_settings(
+        PEN_REPORTS,
+        &settings
+    )));
+}
+
+#[test]
+fn test_settings_pressure_curve() {
+    let reports = [
+        "08 81 03 00 64 09 00 00 00 00 00 00", // No pressure
+        "08 81 03 00 64 09 00 08 00 00 00 00", // Quarter pressure
+        "08 81 03 00 64 09 00 10 00 00 00 00", // Half pressure
+        "08 81 e0 03 8b 0d ff 1f 00 00 00 00", // Max pressure
+        "08 81 e0 03 8b 0d ff ff 00 00 00 00", // Above max pressure
+    ];
+    let mut settings = Settings::new(51689, 34308);
+    settings.flags = SETTING_PRESSURE_CURVE;
+    // Softer, then flat at the top
+    settings.pressure_curve =
+        std::array::from_fn(|i| (8191 * (i as u32).min(12) * (i as u32).min(12) / 144) as u16);
+    insta::assert_snapshot!(format_reports(&run_reports_settings(&reports, &settings)));
+}
+
+#[test]
+fn test_settings_button_map() {
+    let reports = [
+        "08 e0 01 01 01 00 00 00 00 00 00 00", // Button 1 press
+        "08 e0 01 01 02 00 00 00 00 00 00 00", // Button 2 press
+        "08 e0 01 01 00 10 00 00 00 00 00 00", // Button 13 press
+    ];
+    let mut settings = Settings::new(51689, 34308);
+    settings.flags = SETTING_BUTTON_MAP;
+    // Swap buttons 1 and 13, and disable button 2
+    settings.button_map.swap(0, 12);
+    settings.button_map[1] = 0xff;
+    insta::assert_snapshot!(format_reports(&run_reports_settings(&reports, &settings)));
+}
+
+#[test]
+fn test_settings_wheels() {
+    let reports = [
+        "08 f1 01 01 00 01 00 00 00 00 00 00", // Top wheel CW
+        "08 f1 01 02 00 02 00 00 00 00 00 00", // Bottom wheel CCW
+        "08 f0 01 01 00 01 00 00 00 00 ff fe", // Touch top
+        "08 f0 01 01 00 02 00 00 00 00 ff fe", // Touch top, moving
+    ];
+    let mut settings = Settings::new(51689, 34308);
+    settings.dial_mode = WHEEL_REVERSED;
+    settings.ring_mode = WHEEL_OFF;
+    insta::assert_snapshot!(format_reports(&run_reports_settings(&reports, &settings)));
+}
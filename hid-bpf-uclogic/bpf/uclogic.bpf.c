@@ -12,7 +12,27 @@
 #define VENDOR_REPORT_ID 8
 #define DIAL_REPORT_ID 0xf0
 
+#define V1_VENDOR_REPORT_ID 7
+
 #define REPORT_SIZE 12
+#define V1_REPORT_SIZE 8
+
+#define PROTOCOL_V1 1
+#define PROTOCOL_V2 2
+
+#define COLLECTION_STYLUS (1 << 0)
+#define COLLECTION_PAD (1 << 1)
+#define COLLECTION_DIAL (1 << 2)
+
+#define SETTING_PRESSURE_CURVE (1 << 0)
+#define SETTING_BUTTON_MAP (1 << 1)
+
+#define WHEEL_NORMAL 0
+#define WHEEL_REVERSED 1
+#define WHEEL_OFF 2
+
+#define PRESSURE_CURVE_STEPS 16
+#define BUTTON_NONE 0xff
 
 // === General types ===
 
@@ -96,6 +116,40 @@ union vendor_report {
 	} __attribute__((packed)) dial;
 } __attribute__((packed));
 
+struct stylus_flags_v1 {
+	bool tip_switch: 1;
+	bool barrel_switch: 1;
+	bool tablet_pick: 1;
+	__u8 _padding: 3;
+	// Set when out of range
+	bool in_range: 1;
+	bool _always_set: 1;
+} __attribute__((packed));
+
+union vendor_report_v1 {
+	struct {
+		__u8 report_id;
+		__u8 discriminant;
+	} __attribute__((packed));
+
+	struct {
+		__u8 report_id;
+		struct stylus_flags_v1 flags;
+		__u16 x;
+		__u16 y;
+		__u16 pressure;
+	} __attribute__((packed)) stylus;
+
+	struct {
+		__u8 report_id;
+		__u8 discriminant;
+		__u8 _padding_0;
+		__u8 _padding_1;
+		__u8 btns[2];
+		__u8 _padding[2];
+	} __attribute__((packed)) pad;
+} __attribute__((packed));
+
 union report {
 	struct {
 		__u8 report_id;
@@ -127,6 +181,26 @@ union report {
 	} __attribute__((packed)) dial;
 } __attribute__((packed));
 
+// User settings, applied after translating a report
+struct uclogic_settings {
+	// Active area, in device units before rotation
+	__u32 area_x;
+	__u32 area_y;
+	__u32 area_width;
+	__u32 area_height;
+	// Quarter turns clockwise
+	__u32 rotation;
+	__u16 pressure_max;
+	// Output pressure at i / PRESSURE_CURVE_STEPS of pressure_max
+	__u16 pressure_curve[PRESSURE_CURVE_STEPS + 1];
+	__u8 flags;
+	__u8 ring_mode;
+	__u8 dial_mode;
+	__u8 _padding;
+	// Input button bit for each output button bit, or BUTTON_NONE
+	__u8 button_map[64];
+};
+
 #define sizeof_member(type, member) \
 	sizeof((*(__typeof__(type)*)0).member)
 
@@ -136,10 +210,35 @@ _Static_assert(sizeof_member(union vendor_report, pad) == REPORT_SIZE, "");
 _Static_assert(sizeof_member(union vendor_report, touch) == REPORT_SIZE, "");
 _Static_assert(sizeof_member(union vendor_report, dial) == REPORT_SIZE, "");
 _Static_assert(sizeof_member(union vendor_report, stylus) == REPORT_SIZE, "");
+_Static_assert(sizeof_member(union vendor_report_v1, pad) == V1_REPORT_SIZE, "");
+_Static_assert(sizeof_member(union vendor_report_v1, stylus) == V1_REPORT_SIZE, "");
 _Static_assert(sizeof_member(union report, pad) == REPORT_SIZE, "");
 _Static_assert(sizeof_member(union report, dial) == REPORT_SIZE, "");
 _Static_assert(sizeof_member(union report, stylus) == REPORT_SIZE, "");
 
+// The generated descriptor is checked against the description of these
+// layouts in src/reports.rs, keep them in sync
+#define offsetof(type, member) __builtin_offsetof(type, member)
+
+_Static_assert(offsetof(union report, stylus.pressure) == 8, "");
+_Static_assert(offsetof(union report, stylus.x_tilt) == 10, "");
+_Static_assert(offsetof(union report, stylus.y_tilt) == 11, "");
+_Static_assert(offsetof(union report, pad.x) == 2, "");
+_Static_assert(offsetof(union report, pad.y) == 3, "");
+_Static_assert(offsetof(union report, pad.btns) == 4, "");
+_Static_assert(REPORT_NUM_BTN_BITS == 64, "");
+_Static_assert(offsetof(union report, dial.x) == 2, "");
+_Static_assert(offsetof(union report, dial.y) == 3, "");
+_Static_assert(offsetof(union report, dial.delta_1) == 5, "");
+_Static_assert(offsetof(union report, dial.delta_2) == 6, "");
+
+// Written by src/loaded.rs, keep them in sync
+_Static_assert(offsetof(struct uclogic_settings, pressure_max) == 20, "");
+_Static_assert(offsetof(struct uclogic_settings, pressure_curve) == 22, "");
+_Static_assert(offsetof(struct uclogic_settings, flags) == 56, "");
+_Static_assert(offsetof(struct uclogic_settings, button_map) == 60, "");
+_Static_assert(sizeof(struct uclogic_settings) == 124, "");
+
 #ifndef TEST
 static inline
 #endif
@@ -208,6 +307,153 @@ __u8 fixup_report(__u8 *new_report, const __u8 *old_report, struct state *st) {
 	return 1;
 }
 
+#ifndef TEST
+static inline
+#endif
+__u8 fixup_report_v1(__u8 *new_report, const __u8 *old_report, struct state *st __attribute__((unused))) {
+	const union vendor_report_v1 *v = (union vendor_report_v1*)old_report;
+	union report *r = (union report *)new_report;
+
+	if (v->report_id != V1_VENDOR_REPORT_ID)
+		return 0;
+
+	if (v->discriminant == 0xe0) {
+		// Pad event
+		r->pad.report_id = PAD_REPORT_ID;
+		r->pad.btn_stylus = 0;
+		r->pad.x = 0;
+		r->pad.y = 0;
+		__builtin_memset(r->pad.btns, 0, sizeof(r->pad.btns));
+		__builtin_memcpy(r->pad.btns, v->pad.btns, sizeof(v->pad.btns));
+	} else {
+		// Stylus event
+		r->stylus.report_id = VENDOR_REPORT_ID;
+		r->stylus.flags = (struct stylus_flags) {
+			.tip_switch = v->stylus.flags.tip_switch,
+			.barrel_switch = v->stylus.flags.barrel_switch,
+			.secondary_barrel_switch = v->stylus.flags.tablet_pick,
+			.in_range = !v->stylus.flags.in_range,
+		};
+		r->stylus.x = v->stylus.x;
+		r->stylus.y = v->stylus.y;
+		r->stylus.pressure = v->stylus.pressure;
+		r->stylus.x_tilt = 0;
+		r->stylus.y_tilt = 0;
+	}
+
+	return 1;
+}
+
+static inline __u32 min_u32(__u32 a, __u32 b) {
+	return a < b ? a : b;
+}
+
+#ifndef TEST
+static inline
+#endif
+__u8 apply_settings(__u8 *new_report, const __u8 *old_report, const struct uclogic_settings *s) {
+	union report *r = (union report *)new_report;
+
+	switch (new_report[0]) {
+	case VENDOR_REPORT_ID: {
+		__u32 w = s->area_width;
+		__u32 h = s->area_height;
+		__u32 x = r->stylus.x > s->area_x ? min_u32(r->stylus.x - s->area_x, w) : 0;
+		__u32 y = r->stylus.y > s->area_y ? min_u32(r->stylus.y - s->area_y, h) : 0;
+		__u8 tx = r->stylus.x_tilt;
+		__u8 ty = r->stylus.y_tilt;
+
+		switch (s->rotation) {
+		case 1:
+			r->stylus.x = h - y;
+			r->stylus.y = x;
+			r->stylus.x_tilt = -ty;
+			r->stylus.y_tilt = tx;
+			break;
+		case 2:
+			r->stylus.x = w - x;
+			r->stylus.y = h - y;
+			r->stylus.x_tilt = -tx;
+			r->stylus.y_tilt = -ty;
+			break;
+		case 3:
+			r->stylus.x = y;
+			r->stylus.y = w - x;
+			r->stylus.x_tilt = ty;
+			r->stylus.y_tilt = -tx;
+			break;
+		default:
+			r->stylus.x = x;
+			r->stylus.y = y;
+			break;
+		}
+
+		if ((s->flags & SETTING_PRESSURE_CURVE) && s->pressure_max) {
+			// Linear between the points of the curve, without signed division
+			__u32 max = s->pressure_max;
+			__u32 pos = min_u32(r->stylus.pressure, max) * PRESSURE_CURVE_STEPS;
+			__u32 i = pos / max;
+			__u32 frac = pos % max;
+
+			if (i >= PRESSURE_CURVE_STEPS) {
+				r->stylus.pressure = s->pressure_curve[PRESSURE_CURVE_STEPS];
+			} else {
+				__u32 lo = s->pressure_curve[i];
+				__u32 hi = s->pressure_curve[i + 1];
+				r->stylus.pressure = hi >= lo
+					? lo + (hi - lo) * frac / max
+					: lo - (lo - hi) * frac / max;
+			}
+		}
+		break;
+	}
+	case PAD_REPORT_ID:
+		if (s->flags & SETTING_BUTTON_MAP) {
+			__u8 btns[sizeof(r->pad.btns)] = {};
+
+			for (__u32 i = 0; i < REPORT_NUM_BTN_BITS; i++) {
+				__u8 src = s->button_map[i];
+
+				if (src < REPORT_NUM_BTN_BITS && (r->pad.btns[src / 8] >> (src % 8)) & 1)
+					btns[i / 8] |= 1 << (i % 8);
+			}
+			__builtin_memcpy(r->pad.btns, btns, sizeof(btns));
+		}
+		break;
+	case DIAL_REPORT_ID: {
+		// Touch ring events come from discriminant 0xf0, dials from 0xf1
+		__u8 mode = old_report[1] == 0xf0 ? s->ring_mode : s->dial_mode;
+
+		if (mode == WHEEL_OFF)
+			return 0;
+		if (mode == WHEEL_REVERSED) {
+			r->dial.delta_1 = -r->dial.delta_1;
+			r->dial.delta_2 = -r->dial.delta_2;
+		}
+		break;
+	}
+	}
+
+	return 1;
+}
+
+// Drop reports for collections the descriptor does not have
+#ifndef TEST
+static inline
+#endif
+__u8 report_enabled(const __u8 *new_report, __u32 collections) {
+	switch (new_report[0]) {
+	case VENDOR_REPORT_ID:
+		return !!(collections & COLLECTION_STYLUS);
+	case PAD_REPORT_ID:
+		return !!(collections & COLLECTION_PAD);
+	case DIAL_REPORT_ID:
+		return !!(collections & COLLECTION_DIAL);
+	default:
+		return 0;
+	}
+}
+
 #ifndef TEST
 
 #define SEC(name) __attribute__((section(name)))
@@ -250,10 +496,29 @@ extern __u8 *hid_bpf_get_data(struct hid_bpf_ctx *ctx,
 
 SEC(".rodata.uclogic_config")
 struct uclogic_config {
+	__u32 protocol;
+	__u32 collections;
 	__u32 new_rdesc_size;
-	__u8 new_rdesc[384];
+
+	// Not used here, recorded for hid-bpf-uclogic status
+	__u32 hid_id;
+	__u32 magic_bytes_size;
+	char firmware[256];
+	__u8 magic_bytes[256];
+
+	struct uclogic_settings settings;
 } uclogic_config;
 
+// Read and written by src/loaded.rs, keep them in sync
+_Static_assert(offsetof(struct uclogic_config, firmware) == 20, "");
+_Static_assert(offsetof(struct uclogic_config, magic_bytes) == 276, "");
+_Static_assert(offsetof(struct uclogic_config, settings) == 532, "");
+_Static_assert(sizeof(struct uclogic_config) == 656, "");
+
+// Shrunk by the loader to new_rdesc_size
+SEC(".rodata.uclogic_rdesc")
+__u8 uclogic_rdesc[HID_MAX_DESCRIPTOR_SIZE];
+
 SEC("struct_ops/hid_device_event")
 int uclogic_fix_event(unsigned long long *ctx)
 {
@@ -263,16 +528,20 @@ int uclogic_fix_event(unsigned long long *ctx)
 	if (rtype != HID_INPUT_REPORT)
 		return 0;
 
+	bool v1 = uclogic_config.protocol == PROTOCOL_V1;
 	__u8 *data = hid_bpf_get_data(hid_ctx, 0, REPORT_SIZE);
 	__s32 size = hid_ctx->size;
 
-	if (!data || size < REPORT_SIZE)
+	if (!data || size < (v1 ? V1_REPORT_SIZE : REPORT_SIZE))
 		return 0;
 
 	__u8 new_data[REPORT_SIZE];
 	static struct state state;
 
-	if (fixup_report(new_data, data, &state)) {
+	__u8 fixed = v1 ? fixup_report_v1(new_data, data, &state) : fixup_report(new_data, data, &state);
+
+	if (fixed && apply_settings(new_data, data, &uclogic_config.settings)
+		&& report_enabled(new_data, uclogic_config.collections)) {
 		__builtin_memcpy(data, new_data, REPORT_SIZE);
 		return REPORT_SIZE;
 	} else {
@@ -289,8 +558,18 @@ int uclogic_fix_rdesc(unsigned long long *ctx)
 	if (!data)
 		return 0;
 
-	__builtin_memcpy(data, uclogic_config.new_rdesc, sizeof(uclogic_config.new_rdesc));
-	return uclogic_config.new_rdesc_size;
+	__u32 size = uclogic_config.new_rdesc_size;
+
+	if (size > HID_MAX_DESCRIPTOR_SIZE)
+		return 0;
+
+	for (__u32 i = 0; i < HID_MAX_DESCRIPTOR_SIZE; i++) {
+		if (i >= size)
+			break;
+		data[i] = uclogic_rdesc[i];
+	}
+
+	return size;
 }
 
 SEC(".struct_ops.link")
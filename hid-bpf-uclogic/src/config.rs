@@ -0,0 +1,453 @@
+//! User settings, applied when loading
+//!
+//! Settings are read from `config.toml` in `/etc/hid-bpf-uclogic` and then
+//! in `~/.config/hid-bpf-uclogic`, or from the file given with `--config`.
+//! Each `[device."..."]` table applies to devices with that USB id, like
+//! `256c:0064`, or with a matching firmware ID, like `GM001_T207_*`.
+//! Firmware ID tables take precedence over USB id tables, and later files
+//! over earlier ones, one setting at a time.
+
+use std::{
+    collections::BTreeMap,
+    fmt::Display,
+    path::{Path, PathBuf},
+};
+
+use eyre::{Context, Result, bail, eyre};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    descriptor::ParsedDeviceInfo,
+    devices::{UsbId, glob_match},
+    loaded::{NUM_BUTTON_BITS, PRESSURE_CURVE_STEPS, Settings},
+};
+
+static SYSTEM_FILE: &str = "/etc/hid-bpf-uclogic/config.toml";
+
+/// Settings for all devices, from one or more files
+#[derive(Debug, Default)]
+pub(crate) struct UserConfig {
+    files: Vec<ConfigFile>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ConfigFile {
+    #[serde(default)]
+    device: BTreeMap<String, DeviceConfig>,
+}
+
+/// Settings for one device, unset ones are left as the device has them
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub(crate) struct DeviceConfig {
+    /// `[input, output]` points, as fractions of the maximum pressure
+    pub pressure_curve: Option<Vec<[f64; 2]>>,
+    /// Fractions of the whole tablet
+    pub active_area: Option<Area>,
+    /// Degrees clockwise the tablet is turned, a multiple of 90
+    pub rotation: Option<u32>,
+    /// Physical button for each button, counting from 1, or 0 to disable
+    pub buttons: Option<Vec<u8>>,
+    pub ring: Option<WheelMode>,
+    pub dials: Option<WheelMode>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Area {
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+}
+
+/// What the touch ring or dials report
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum WheelMode {
+    /// Scroll wheel
+    Normal,
+    /// Scroll wheel in the other direction
+    Reversed,
+    /// Nothing, and left out of the descriptor if there is nothing else
+    Off,
+}
+
+impl UserConfig {
+    /// Load the system and user files, or only `path` if given
+    pub(crate) fn load(path: Option<&Path>) -> Result<Self> {
+        if let Some(path) = path {
+            return Self::load_from(&[path.to_owned()], true);
+        }
+
+        let mut paths = vec![PathBuf::from(SYSTEM_FILE)];
+        paths.extend(crate::devices::config_dir().map(|d| d.join("config.toml")));
+        Self::load_from(&paths, false)
+    }
+
+    /// Missing files are skipped unless `required`
+    pub(crate) fn load_from(paths: &[PathBuf], required: bool) -> Result<Self> {
+        let mut config = Self::default();
+        for path in paths {
+            let text = match std::fs::read_to_string(path) {
+                Ok(text) => text,
+                Err(e) if !required && e.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(e) => Err(e).wrap_err_with(|| format!("Reading {}", path.display()))?,
+            };
+            let file = toml::from_str(&text)
+                .map_err(|e| eyre!("{e}"))
+                .wrap_err_with(|| format!("Parsing {}", path.display()))?;
+            config.files.push(file);
+        }
+        Ok(config)
+    }
+
+    /// Merged settings for a device
+    pub(crate) fn for_device(&self, usb_id: Option<UsbId>, firmware: &str) -> DeviceConfig {
+        let mut config = DeviceConfig::default();
+        for file in &self.files {
+            let (by_usb, by_firmware): (Vec<_>, Vec<_>) = file
+                .device
+                .iter()
+                .partition(|(key, _)| UsbId::try_from(key.to_string()).is_ok());
+
+            for (key, device) in by_usb {
+                if usb_id.is_some_and(|id| UsbId::try_from(key.clone()) == Ok(id)) {
+                    config.merge(device);
+                }
+            }
+            for (key, device) in by_firmware {
+                if glob_match(key, firmware) {
+                    config.merge(device);
+                }
+            }
+        }
+        config
+    }
+}
+
+impl DeviceConfig {
+    fn merge(&mut self, other: &Self) {
+        fn set<T: Clone>(dst: &mut Option<T>, src: &Option<T>) {
+            if src.is_some() {
+                dst.clone_from(src);
+            }
+        }
+
+        set(&mut self.pressure_curve, &other.pressure_curve);
+        set(&mut self.active_area, &other.active_area);
+        set(&mut self.rotation, &other.rotation);
+        set(&mut self.buttons, &other.buttons);
+        set(&mut self.ring, &other.ring);
+        set(&mut self.dials, &other.dials);
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Settings for the BPF program, and the device as the descriptor
+    /// describes it with them
+    pub(crate) fn apply(&self, parsed: &mut ParsedDeviceInfo) -> Result<Settings> {
+        let mut settings = Settings::new(parsed.x_max, parsed.y_max, parsed.pres_max);
+
+        if let Some(area) = &self.active_area {
+            settings.area = area.to_device(parsed.x_max, parsed.y_max)?;
+        }
+        if let Some(rotation) = self.rotation {
+            if rotation % 90 != 0 || rotation >= 360 {
+                bail!("Rotation must be 0, 90, 180 or 270 degrees, not {rotation}");
+            }
+            settings.rotation = rotation / 90;
+        }
+        if let Some(points) = &self.pressure_curve {
+            settings.pressure_curve = Some(pressure_curve(points, parsed.pres_max)?);
+        }
+        if let Some(buttons) = &self.buttons {
+            settings.button_map = Some(button_map(buttons, parsed.num_btns)?);
+        }
+        settings.ring = self.ring.unwrap_or(WheelMode::Normal);
+        settings.dials = self.dials.unwrap_or(WheelMode::Normal);
+
+        let [_, _, width, height] = settings.area;
+        (parsed.x_max, parsed.y_max) = if settings.rotation.is_multiple_of(2) {
+            (width, height)
+        } else {
+            (height, width)
+        };
+        if settings.ring == WheelMode::Off {
+            parsed.caps.ring = false;
+        }
+        if settings.dials == WheelMode::Off {
+            parsed.caps.dials = 0;
+        }
+
+        Ok(settings)
+    }
+}
+
+impl Area {
+    /// `[x, y, width, height]` in device units
+    fn to_device(self, x_max: u32, y_max: u32) -> Result<[u32; 4]> {
+        let Self {
+            x,
+            y,
+            width,
+            height,
+        } = self;
+        let fractions = [x, y, width, height, x + width, y + height];
+        if fractions.iter().any(|f| !(0.0..=1.0).contains(f)) || width <= 0.0 || height <= 0.0 {
+            bail!("Active area must be within the tablet, as fractions from 0 to 1");
+        }
+
+        let scale = |f: f64, max: u32| (f * max as f64).round() as u32;
+        // Rounding must not take the area past the edge
+        let x = scale(x, x_max).min(x_max.saturating_sub(1));
+        let y = scale(y, y_max).min(y_max.saturating_sub(1));
+        let width = scale(width, x_max).min(x_max - x).max(1);
+        let height = scale(height, y_max).min(y_max - y).max(1);
+        Ok([x, y, width, height])
+    }
+}
+
+/// Output at each step, linear between the points, which implicitly start
+/// at `[0, 0]` and end at `[1, 1]`
+fn pressure_curve(points: &[[f64; 2]], pres_max: u16) -> Result<[u16; PRESSURE_CURVE_STEPS + 1]> {
+    if points.iter().flatten().any(|f| !(0.0..=1.0).contains(f)) {
+        bail!("Pressure curve points must be fractions from 0 to 1");
+    }
+    if points.windows(2).any(|w| w[0][0] >= w[1][0]) {
+        bail!("Pressure curve inputs must be increasing");
+    }
+
+    let mut all = Vec::with_capacity(points.len() + 2);
+    if points.first().is_none_or(|p| p[0] > 0.0) {
+        all.push([0.0, 0.0]);
+    }
+    all.extend_from_slice(points);
+    if points.last().is_none_or(|p| p[0] < 1.0) {
+        all.push([1.0, 1.0]);
+    }
+
+    Ok(std::array::from_fn(|i| {
+        let input = i as f64 / PRESSURE_CURVE_STEPS as f64;
+        let end = all.iter().position(|p| p[0] >= input).unwrap();
+        let output = match end {
+            0 => all[0][1],
+            _ => {
+                let ([x0, y0], [x1, y1]) = (all[end - 1], all[end]);
+                y0 + (y1 - y0) * (input - x0) / (x1 - x0)
+            }
+        };
+        (output * pres_max as f64).round() as u16
+    }))
+}
+
+fn button_map(buttons: &[u8], num_btns: u8) -> Result<[Option<u8>; NUM_BUTTON_BITS]> {
+    if buttons.len() > num_btns as usize {
+        bail!("{} buttons given, the device has {num_btns}", buttons.len());
+    }
+    if let Some(b) = buttons.iter().find(|&&b| b > num_btns) {
+        bail!("Button {b} does not exist, the device has {num_btns}");
+    }
+
+    let mut map = std::array::from_fn(|i| Some(i as u8));
+    for (dst, &src) in map.iter_mut().zip(buttons) {
+        *dst = src.checked_sub(1);
+    }
+    Ok(map)
+}
+
+impl Display for WheelMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WheelMode::Normal => write!(f, "normal"),
+            WheelMode::Reversed => write!(f, "reversed"),
+            WheelMode::Off => write!(f, "off"),
+        }
+    }
+}
+
+impl WheelMode {
+    /// `WHEEL_*` in the BPF program
+    pub(crate) fn to_raw(self) -> u8 {
+        match self {
+            WheelMode::Normal => 0,
+            WheelMode::Reversed => 1,
+            WheelMode::Off => 2,
+        }
+    }
+
+    pub(crate) fn from_raw(raw: u8) -> Result<Self> {
+        match raw {
+            0 => Ok(WheelMode::Normal),
+            1 => Ok(WheelMode::Reversed),
+            2 => Ok(WheelMode::Off),
+            _ => bail!("Unknown wheel mode {raw}"),
+        }
+    }
+}
+
+impl Display for DeviceConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut settings = Vec::new();
+        if let Some(points) = &self.pressure_curve {
+            settings.push(format!("pressure curve with {} points", points.len()));
+        }
+        if let Some(a) = &self.active_area {
+            settings.push(format!(
+                "active area {}x{} at ({}, {})",
+                a.width, a.height, a.x, a.y
+            ));
+        }
+        if let Some(rotation) = self.rotation {
+            settings.push(format!("rotation {rotation} degrees"));
+        }
+        if let Some(buttons) = &self.buttons {
+            settings.push(format!("{} buttons remapped", buttons.len()));
+        }
+        if let Some(ring) = self.ring {
+            settings.push(format!("ring {ring}"));
+        }
+        if let Some(dials) = self.dials {
+            settings.push(format!("dials {dials}"));
+        }
+        if settings.is_empty() {
+            write!(f, "no settings")
+        } else {
+            write!(f, "{}", settings.join(", "))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::descriptor::DeviceInfo;
+
+    fn gaomon_m7() -> ParsedDeviceInfo {
+        let text = include_str!("../fixtures/device-info/gaomon-m7.txt");
+        let mut dev = DeviceInfo::from_str(text).unwrap().parse().unwrap();
+        dev.caps.ring = true;
+        dev
+    }
+
+    #[test]
+    fn test_for_device() {
+        let dir =
+            std::env::temp_dir().join(format!("hid-bpf-uclogic-config-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let system = dir.join("system.toml");
+        let user = dir.join("user.toml");
+        std::fs::write(
+            &system,
+            r#"
+            [device."256c:0064"]
+            rotation = 90
+            ring = "reversed"
+
+            [device."GM001_T207_*"]
+            rotation = 180
+            "#,
+        )
+        .unwrap();
+        std::fs::write(
+            &user,
+            r#"
+            [device."256c:0064"]
+            ring = "off"
+            active-area = { x = 0.0, y = 0.0, width = 0.5, height = 0.5 }
+            "#,
+        )
+        .unwrap();
+
+        let config = UserConfig::load_from(&[system, user, dir.join("missing.toml")], false);
+        std::fs::remove_dir_all(&dir).unwrap();
+        let config = config.unwrap();
+
+        let m7 = UsbId::try_from("256c:0064".to_owned()).ok();
+        let device = config.for_device(m7, "GM001_T207_210524");
+        assert_eq!(device.rotation, Some(180));
+        assert_eq!(device.ring, Some(WheelMode::Off));
+        assert!(device.active_area.is_some());
+
+        let device = config.for_device(m7, "HUION_T216_220819");
+        assert_eq!(device.rotation, Some(90));
+        assert!(config.for_device(None, "HUION_T216_220819").is_empty());
+    }
+
+    #[test]
+    fn test_bad_config() {
+        let parse = |text| toml::from_str::<ConfigFile>(text).is_ok();
+        assert!(parse("[device.\"256c:0064\"]\nrotation = 90"));
+        assert!(!parse("[device.\"256c:0064\"]\nrotate = 90"));
+        assert!(!parse("[device.\"256c:0064\"]\nring = \"sideways\""));
+        assert!(UserConfig::load_from(&["/nonexistent/config.toml".into()], true).is_err());
+    }
+
+    #[test]
+    fn test_apply() {
+        let mut dev = gaomon_m7();
+        let (x_max, y_max) = (dev.x_max, dev.y_max);
+        let settings = DeviceConfig::default().apply(&mut dev).unwrap();
+        assert_eq!(settings.area, [0, 0, x_max, y_max]);
+        assert_eq!((dev.x_max, dev.y_max), (x_max, y_max));
+
+        let config = DeviceConfig {
+            active_area: Some(Area {
+                x: 0.5,
+                y: 0.0,
+                width: 0.5,
+                height: 0.25,
+            }),
+            rotation: Some(270),
+            ring: Some(WheelMode::Off),
+            ..Default::default()
+        };
+        let settings = config.apply(&mut dev).unwrap();
+        let [_, _, width, height] = settings.area;
+        assert_eq!(settings.area[0] + width, x_max);
+        assert_eq!(height, (y_max as f64 / 4.0).round() as u32);
+        assert_eq!(settings.rotation, 3);
+        assert_eq!((dev.x_max, dev.y_max), (height, width));
+        assert!(!dev.caps.ring);
+
+        let config = DeviceConfig {
+            rotation: Some(45),
+            ..Default::default()
+        };
+        assert!(config.apply(&mut gaomon_m7()).is_err());
+    }
+
+    #[test]
+    fn test_pressure_curve() {
+        let linear = pressure_curve(&[], 1600).unwrap();
+        assert_eq!(linear, std::array::from_fn(|i| i as u16 * 100));
+
+        let soft = pressure_curve(&[[0.5, 0.75]], 1600).unwrap();
+        assert_eq!(soft[0], 0);
+        assert_eq!(soft[4], 600);
+        assert_eq!(soft[8], 1200);
+        assert_eq!(soft[12], 1400);
+        assert_eq!(soft[16], 1600);
+
+        let clipped = pressure_curve(&[[0.0, 0.25], [0.5, 1.0], [1.0, 1.0]], 1600).unwrap();
+        assert_eq!(clipped[0], 400);
+        assert_eq!(clipped[8], 1600);
+        assert_eq!(clipped[16], 1600);
+
+        assert!(pressure_curve(&[[0.5, 0.5], [0.25, 0.75]], 1600).is_err());
+        assert!(pressure_curve(&[[0.5, 1.5]], 1600).is_err());
+    }
+
+    #[test]
+    fn test_button_map() {
+        let map = button_map(&[2, 1, 0], 13).unwrap();
+        assert_eq!(map[..4], [Some(1), Some(0), None, Some(3)]);
+        assert_eq!(map[63], Some(63));
+
+        assert!(button_map(&[14], 13).is_err());
+        assert!(button_map(&[1; 14], 13).is_err());
+    }
+}
@@ -0,0 +1,280 @@
+//! Loading the driver as supported devices are plugged in
+
+use std::{
+    collections::HashSet,
+    ffi::{OsStr, OsString},
+    path::PathBuf,
+};
+
+use eyre::{Context, Result};
+
+use crate::{
+    devices::DeviceDb,
+    sysfs::SysfsPath,
+    uevent::{EventSource, Received, Uevent},
+};
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum Action {
+    /// Load the driver for a USB device, whose vendor interface has this HID id
+    Load { usb: PathBuf, hid_id: i32 },
+    /// A HID device is gone, so its pin is stale if it has one
+    Unpin(i32),
+}
+
+/// Supported devices that are already plugged in
+pub(crate) fn coldplug(sysfs: &SysfsPath, db: &DeviceDb) -> Result<Vec<Action>> {
+    let mut actions = Vec::new();
+    for (usb, hids) in crate::find_hid_devices(sysfs)? {
+        actions.extend(load_action(db, &usb, &hids)?);
+    }
+    actions.sort();
+    Ok(actions)
+}
+
+fn load_action(
+    db: &DeviceDb,
+    usb: &SysfsPath,
+    hids: &[(u8, OsString, SysfsPath)],
+) -> Result<Option<Action>> {
+    if !crate::device_supported(usb, db)? {
+        return Ok(None);
+    }
+    // Device info can only be queried over USB
+    if crate::device_bus(usb)? != Some(crate::Bus::Usb) {
+        return Ok(None);
+    }
+
+    let hid_id = hids
+        .iter()
+        .find(|(num, ..)| *num == 0)
+        .and_then(|(_, name, _)| crate::parse_hid_id(name));
+    let Some(hid_id) = hid_id else {
+        return Ok(None);
+    };
+
+    Ok(Some(Action::Load {
+        usb: usb.recover_path()?,
+        hid_id,
+    }))
+}
+
+/// Decide what an event means for us, if anything
+pub(crate) fn handle(sysfs: &SysfsPath, db: &DeviceDb, event: &Uevent) -> Result<Option<Action>> {
+    match (event.subsystem.as_str(), event.action.as_str()) {
+        // A USB device is bound after its interfaces are probed, so its HID
+        // devices all exist by now
+        ("usb", "bind") if event.devtype.as_deref() == Some("usb_device") => {
+            let Some(usb) = sysfs.sub(event.devpath.trim_start_matches('/'))? else {
+                // Already gone
+                return Ok(None);
+            };
+            let usb_hid = crate::find_hid_devices(sysfs)?;
+            let Some(hids) = usb_hid.get(&usb) else {
+                return Ok(None);
+            };
+            load_action(db, &usb, hids)
+        }
+        ("hid", "remove") => Ok(crate::parse_hid_id(OsStr::new(event.name())).map(Action::Unpin)),
+        _ => Ok(None),
+    }
+}
+
+/// Act on devices already plugged in, then on events until there are none
+///
+/// The source should be listening before this is called, so that devices
+/// plugged in meanwhile are not missed. The same goes for when events are
+/// dropped. Failures are reported and otherwise ignored.
+pub(crate) fn run(
+    sysfs: &SysfsPath,
+    db: &DeviceDb,
+    source: &mut dyn EventSource,
+    mut act: impl FnMut(&Action) -> Result<()>,
+) -> Result<()> {
+    // Devices are queried when loading, which disables them, so only do it
+    // once for each
+    let mut loaded = HashSet::new();
+
+    let mut dispatch = |action: Action| {
+        match action {
+            Action::Load { hid_id, .. } if loaded.contains(&hid_id) => return,
+            Action::Unpin(hid_id) => {
+                loaded.remove(&hid_id);
+            }
+            Action::Load { .. } => (),
+        }
+        match act(&action) {
+            Ok(()) => {
+                if let Action::Load { hid_id, .. } = action {
+                    loaded.insert(hid_id);
+                }
+            }
+            Err(e) => eprintln!("Error: {e:?}"),
+        }
+    };
+
+    for action in coldplug(sysfs, db)? {
+        dispatch(action);
+    }
+
+    while let Some(received) = source.next_event()? {
+        let event = match received {
+            Received::Event(event) => event,
+            Received::Overflow => {
+                eprintln!("Device events were dropped, looking for devices again");
+                match coldplug(sysfs, db) {
+                    Ok(actions) => actions.into_iter().for_each(&mut dispatch),
+                    Err(e) => eprintln!("Error: {e:?}"),
+                }
+                continue;
+            }
+        };
+        let action = handle(sysfs, db, &event)
+            .wrap_err_with(|| format!("Handling {} of {}", event.action, event.devpath));
+        match action {
+            Ok(Some(action)) => dispatch(action),
+            Ok(None) => (),
+            Err(e) => eprintln!("Error: {e:?}"),
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        fixture::{FakeSysfs, UsbDevice},
+        uevent::parse_events,
+    };
+
+    #[test]
+    fn test_daemon() {
+        let fake = FakeSysfs::new("daemon");
+        fake.usb(
+            "1-3",
+            UsbDevice {
+                hid_id: 0x49,
+                ..UsbDevice::GAOMON_M7
+            },
+        );
+        fake.usb(
+            "1-4",
+            UsbDevice {
+                hid_id: 0x4c,
+                ..UsbDevice::HUION_DIAL_2
+            },
+        );
+        fake.usb("1-5", UsbDevice::RECEIVER);
+
+        let events = parse_events(
+            "ACTION=bind
+            DEVPATH=/devices/pci0000:00/0000:00:14.0/usb1/1-5
+            SUBSYSTEM=usb
+            DEVTYPE=usb_device
+
+            ACTION=add
+            DEVPATH=/devices/pci0000:00/0000:00:14.0/usb1/1-4
+            SUBSYSTEM=usb
+            DEVTYPE=usb_device
+
+            ACTION=bind
+            DEVPATH=/devices/pci0000:00/0000:00:14.0/usb1/1-4/1-4:1.0
+            SUBSYSTEM=usb
+            DEVTYPE=usb_interface
+
+            ACTION=bind
+            DEVPATH=/devices/pci0000:00/0000:00:14.0/usb1/1-4
+            SUBSYSTEM=usb
+            DEVTYPE=usb_device
+
+            ACTION=remove
+            DEVPATH=/devices/pci0000:00/0000:00:14.0/usb1/1-3/1-3:1.0/0003:256C:0064.0049
+            SUBSYSTEM=hid
+
+            ACTION=bind
+            DEVPATH=/devices/pci0000:00/0000:00:14.0/usb1/1-9
+            SUBSYSTEM=usb
+            DEVTYPE=usb_device",
+        );
+
+        let sysfs = fake.sysfs();
+        let sysfs = sysfs.root().unwrap();
+        let db = DeviceDb::load_from(&[]).unwrap();
+
+        assert_eq!(
+            coldplug(&sysfs, &db).unwrap(),
+            [
+                Action::Load {
+                    usb: fake.path("1-3"),
+                    hid_id: 0x49,
+                },
+                Action::Load {
+                    usb: fake.path("1-4"),
+                    hid_id: 0x4c,
+                },
+            ]
+        );
+
+        let mut actions = Vec::new();
+        let events: Vec<_> = events.into_iter().map(Received::Event).collect();
+        run(&sysfs, &db, &mut events.into_iter(), |a| {
+            actions.push(a.clone());
+            Ok(())
+        })
+        .unwrap();
+
+        // 1-4 is only loaded once, though it was bound after starting
+        assert_eq!(
+            actions,
+            [
+                Action::Load {
+                    usb: fake.path("1-3"),
+                    hid_id: 0x49,
+                },
+                Action::Load {
+                    usb: fake.path("1-4"),
+                    hid_id: 0x4c,
+                },
+                Action::Unpin(0x49),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_overflow() {
+        let fake = FakeSysfs::new("daemon-overflow");
+        fake.usb("1-3", UsbDevice::GAOMON_M7);
+
+        let sysfs = fake.sysfs();
+        let sysfs = sysfs.root().unwrap();
+        let db = DeviceDb::load_from(&[]).unwrap();
+
+        // 1-4 is plugged in while events are dropped
+        let mut actions = Vec::new();
+        let mut events = vec![Received::Overflow].into_iter();
+        run(&sysfs, &db, &mut events, |a| {
+            if actions.is_empty() {
+                fake.usb("1-4", UsbDevice::HUION_DIAL_2);
+            }
+            actions.push(a.clone());
+            Ok(())
+        })
+        .unwrap();
+
+        assert_eq!(
+            actions,
+            [
+                Action::Load {
+                    usb: fake.path("1-3"),
+                    hid_id: 0x4c,
+                },
+                Action::Load {
+                    usb: fake.path("1-4"),
+                    hid_id: 0x58,
+                },
+            ]
+        );
+    }
+}
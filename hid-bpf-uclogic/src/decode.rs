@@ -0,0 +1,692 @@
+//! Decoder for HID report descriptors
+//!
+//! Turns a descriptor into one line per item, like hid-decode from
+//! hid-tools, and computes where each field is in each report.
+
+use std::fmt::{Display, Write};
+
+use eyre::{Result, bail};
+
+use crate::rdesc::{ItemType, consumer, desktop, digitizers, flags, page};
+
+static PAGE_NAMES: &[(u16, &str)] = &[
+    (page::GENERIC_DESKTOP, "Generic Desktop"),
+    (page::KEYBOARD, "Keyboard"),
+    (page::LED, "LED"),
+    (page::BUTTON, "Button"),
+    (page::CONSUMER, "Consumer"),
+    (page::DIGITIZERS, "Digitizers"),
+];
+
+static USAGE_NAMES: &[(u16, u16, &str)] = &[
+    (page::GENERIC_DESKTOP, desktop::POINTER, "Pointer"),
+    (page::GENERIC_DESKTOP, desktop::MOUSE, "Mouse"),
+    (page::GENERIC_DESKTOP, desktop::JOYSTICK, "Joystick"),
+    (page::GENERIC_DESKTOP, desktop::GAME_PAD, "Game Pad"),
+    (page::GENERIC_DESKTOP, desktop::KEYBOARD, "Keyboard"),
+    (page::GENERIC_DESKTOP, desktop::KEYPAD, "Keypad"),
+    (page::GENERIC_DESKTOP, desktop::X, "X"),
+    (page::GENERIC_DESKTOP, desktop::Y, "Y"),
+    (page::GENERIC_DESKTOP, desktop::Z, "Z"),
+    (page::GENERIC_DESKTOP, desktop::RX, "Rx"),
+    (page::GENERIC_DESKTOP, desktop::RY, "Ry"),
+    (page::GENERIC_DESKTOP, desktop::RZ, "Rz"),
+    (page::GENERIC_DESKTOP, desktop::DIAL, "Dial"),
+    (page::GENERIC_DESKTOP, desktop::WHEEL, "Wheel"),
+    (
+        page::CONSUMER,
+        consumer::CONSUMER_CONTROL,
+        "Consumer Control",
+    ),
+    (
+        page::CONSUMER,
+        consumer::VOLUME_INCREMENT,
+        "Volume Increment",
+    ),
+    (
+        page::CONSUMER,
+        consumer::VOLUME_DECREMENT,
+        "Volume Decrement",
+    ),
+    (page::CONSUMER, consumer::AC_PAN, "AC Pan"),
+    (page::DIGITIZERS, digitizers::DIGITIZER, "Digitizer"),
+    (page::DIGITIZERS, digitizers::PEN, "Pen"),
+    (page::DIGITIZERS, digitizers::STYLUS, "Stylus"),
+    (page::DIGITIZERS, digitizers::FINGER, "Finger"),
+    (page::DIGITIZERS, digitizers::TIP_PRESSURE, "Tip Pressure"),
+    (
+        page::DIGITIZERS,
+        digitizers::BARREL_PRESSURE,
+        "Barrel Pressure",
+    ),
+    (page::DIGITIZERS, digitizers::IN_RANGE, "In Range"),
+    (
+        page::DIGITIZERS,
+        digitizers::TABLET_FUNCTION_KEYS,
+        "Tablet Function Keys",
+    ),
+    (page::DIGITIZERS, digitizers::INVERT, "Invert"),
+    (page::DIGITIZERS, digitizers::X_TILT, "X Tilt"),
+    (page::DIGITIZERS, digitizers::Y_TILT, "Y Tilt"),
+    (page::DIGITIZERS, digitizers::TIP_SWITCH, "Tip Switch"),
+    (page::DIGITIZERS, digitizers::BARREL_SWITCH, "Barrel Switch"),
+    (page::DIGITIZERS, digitizers::ERASER, "Eraser"),
+    (
+        page::DIGITIZERS,
+        digitizers::SECONDARY_BARREL_SWITCH,
+        "Secondary Barrel Switch",
+    ),
+];
+
+/// A short item in a report descriptor
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Item<'a> {
+    /// The whole item, including the prefix
+    pub bytes: &'a [u8],
+    pub item_type: ItemType,
+    pub tag: u8,
+    pub data: &'a [u8],
+}
+
+/// Iterator over the items of a report descriptor
+#[derive(Debug)]
+pub(crate) struct Items<'a> {
+    rest: &'a [u8],
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ReportKind {
+    Input,
+    Output,
+    Feature,
+}
+
+/// A Main item's worth of data in a report
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Field {
+    /// Offset in bits, counting the report ID byte if there is one
+    pub offset: u32,
+    pub size: u32,
+    pub count: u32,
+    pub flags: u32,
+    /// The usage of a variable field, or the possible usages of an array,
+    /// as `page << 16 | id`
+    pub usages: Vec<u32>,
+    pub logical_min: i64,
+    pub logical_max: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Report {
+    pub kind: ReportKind,
+    /// Report ID, 0 if the descriptor has no Report ID items
+    pub id: u8,
+    /// Size in bits, counting the report ID byte if there is one
+    pub bits: u32,
+    /// Variable items get one field per usage
+    pub fields: Vec<Field>,
+}
+
+#[derive(Debug, Clone, Default)]
+struct Globals {
+    usage_page: u16,
+    logical_min: i32,
+    /// Signedness depends on the logical minimum, so keep the raw data
+    logical_max: (u32, usize),
+    report_size: u32,
+    report_id: u8,
+    report_count: u32,
+}
+
+#[derive(Debug, Clone, Default)]
+struct Locals {
+    usages: Vec<u32>,
+    usage_min: Option<u32>,
+    usage_max: Option<u32>,
+}
+
+/// Parser state while going through the items
+#[derive(Debug, Default)]
+struct Decoder {
+    globals: Globals,
+    stack: Vec<Globals>,
+    locals: Locals,
+    depth: usize,
+    reports: Vec<Report>,
+}
+
+impl<'a> Items<'a> {
+    pub(crate) fn new(desc: &'a [u8]) -> Self {
+        Self { rest: desc }
+    }
+}
+
+impl<'a> Iterator for Items<'a> {
+    type Item = Result<Item<'a>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let &prefix = self.rest.first()?;
+        if prefix == 0xfe {
+            self.rest = &[];
+            return Some(Err(eyre::eyre!("Long items are not supported")));
+        }
+
+        let size = match prefix & 0x3 {
+            3 => 4,
+            n => n as usize,
+        };
+        if self.rest.len() < 1 + size {
+            self.rest = &[];
+            return Some(Err(eyre::eyre!("Descriptor ends in the middle of an item")));
+        }
+
+        let (bytes, rest) = self.rest.split_at(1 + size);
+        self.rest = rest;
+        let item_type = match (prefix >> 2) & 0x3 {
+            0 => ItemType::Main,
+            1 => ItemType::Global,
+            2 => ItemType::Local,
+            _ => ItemType::Reserved,
+        };
+        Some(Ok(Item {
+            bytes,
+            item_type,
+            tag: prefix >> 4,
+            data: &bytes[1..],
+        }))
+    }
+}
+
+impl Item<'_> {
+    pub(crate) fn udata(&self) -> u32 {
+        self.data
+            .iter()
+            .rev()
+            .fold(0, |acc, &b| (acc << 8) | b as u32)
+    }
+
+    pub(crate) fn sdata(&self) -> i32 {
+        sign_extend(self.udata(), self.data.len())
+    }
+}
+
+fn sign_extend(value: u32, size: usize) -> i32 {
+    match size {
+        0 => 0,
+        1 => value as u8 as i8 as i32,
+        2 => value as u16 as i16 as i32,
+        _ => value as i32,
+    }
+}
+
+impl Decoder {
+    fn item(&mut self, item: &Item) -> Result<()> {
+        match (item.item_type, item.tag) {
+            (ItemType::Main, 0x8) => self.field(ReportKind::Input, item.udata())?,
+            (ItemType::Main, 0x9) => self.field(ReportKind::Output, item.udata())?,
+            (ItemType::Main, 0xb) => self.field(ReportKind::Feature, item.udata())?,
+            (ItemType::Main, 0xa) => self.depth += 1,
+            (ItemType::Main, 0xc) => {
+                if self.depth == 0 {
+                    bail!("End Collection without Collection");
+                }
+                self.depth -= 1;
+            }
+            (ItemType::Global, 0x0) => self.globals.usage_page = item.udata() as u16,
+            (ItemType::Global, 0x1) => self.globals.logical_min = item.sdata(),
+            (ItemType::Global, 0x2) => self.globals.logical_max = (item.udata(), item.data.len()),
+            (ItemType::Global, 0x7) => self.globals.report_size = item.udata(),
+            (ItemType::Global, 0x8) => {
+                if item.udata() == 0 || item.udata() > 0xff {
+                    bail!("Invalid Report ID {}", item.udata());
+                }
+                self.globals.report_id = item.udata() as u8;
+            }
+            (ItemType::Global, 0x9) => self.globals.report_count = item.udata(),
+            (ItemType::Global, 0xa) => self.stack.push(self.globals.clone()),
+            (ItemType::Global, 0xb) => {
+                self.globals = self
+                    .stack
+                    .pop()
+                    .ok_or_else(|| eyre::eyre!("Pop without Push"))?;
+            }
+            (ItemType::Local, 0x0) => {
+                let usage = self.usage(item);
+                self.locals.usages.push(usage);
+            }
+            (ItemType::Local, 0x1) => self.locals.usage_min = Some(self.usage(item)),
+            (ItemType::Local, 0x2) => self.locals.usage_max = Some(self.usage(item)),
+            _ => (),
+        }
+
+        if item.item_type == ItemType::Main {
+            self.locals = Locals::default();
+        }
+
+        Ok(())
+    }
+
+    /// Extend a usage with the current usage page unless it is a 4-byte one
+    fn usage(&self, item: &Item) -> u32 {
+        if item.data.len() == 4 {
+            item.udata()
+        } else {
+            (self.globals.usage_page as u32) << 16 | item.udata()
+        }
+    }
+
+    fn field(&mut self, kind: ReportKind, item_flags: u32) -> Result<()> {
+        let g = &self.globals;
+
+        let mut usages = std::mem::take(&mut self.locals.usages);
+        if let (Some(min), Some(max)) = (self.locals.usage_min, self.locals.usage_max) {
+            if max < min || max - min > 0xffff {
+                bail!("Invalid usage range");
+            }
+            usages.extend(min..=max);
+        }
+
+        let logical_min = g.logical_min as i64;
+        let logical_max = if logical_min < 0 {
+            sign_extend(g.logical_max.0, g.logical_max.1) as i64
+        } else {
+            g.logical_max.0 as i64
+        };
+
+        let report = match self
+            .reports
+            .iter_mut()
+            .find(|r| r.kind == kind && r.id == g.report_id)
+        {
+            Some(report) => report,
+            None => {
+                self.reports.push(Report {
+                    kind,
+                    id: g.report_id,
+                    bits: if g.report_id == 0 { 0 } else { 8 },
+                    fields: Vec::new(),
+                });
+                self.reports.last_mut().unwrap()
+            }
+        };
+
+        let total = g
+            .report_size
+            .checked_mul(g.report_count)
+            .filter(|&t| t <= 0xffff)
+            .ok_or_else(|| eyre::eyre!("Report field too large"))?;
+
+        let field = |offset, count, usages| Field {
+            offset,
+            size: g.report_size,
+            count,
+            flags: item_flags,
+            usages,
+            logical_min,
+            logical_max,
+        };
+
+        let constant = item_flags & flags::CONSTANT as u32 != 0;
+        let variable = item_flags & flags::VARIABLE as u32 != 0;
+
+        if constant || usages.is_empty() {
+            report
+                .fields
+                .push(field(report.bits, g.report_count, Vec::new()));
+        } else if variable {
+            for i in 0..g.report_count {
+                let usage = usages.get(i as usize).or(usages.last()).copied();
+                report.fields.push(field(
+                    report.bits + i * g.report_size,
+                    1,
+                    usage.into_iter().collect(),
+                ));
+            }
+        } else {
+            report
+                .fields
+                .push(field(report.bits, g.report_count, usages));
+        }
+
+        report.bits += total;
+        Ok(())
+    }
+}
+
+/// Compute the layout of every report in a descriptor
+pub(crate) fn layout(desc: &[u8]) -> Result<Vec<Report>> {
+    let mut decoder = Decoder::default();
+    for item in Items::new(desc) {
+        decoder.item(&item?)?;
+    }
+    Ok(decoder.reports)
+}
+
+/// Usage of the first collection, which says what the device is, as
+/// `page << 16 | id`
+pub(crate) fn application_usage(desc: &[u8]) -> Result<Option<u32>> {
+    let mut decoder = Decoder::default();
+    for item in Items::new(desc) {
+        let item = item?;
+        if (item.item_type, item.tag) == (ItemType::Main, 0xa) {
+            return Ok(decoder.locals.usages.first().copied());
+        }
+        decoder.item(&item)?;
+    }
+    Ok(None)
+}
+
+pub(crate) fn page_name(page: u16) -> String {
+    match PAGE_NAMES.iter().find(|&&(p, _)| p == page) {
+        Some((_, name)) => name.to_string(),
+        None if page >= page::VENDOR => format!("Vendor Defined {page:#06x}"),
+        None => format!("{page:#06x}"),
+    }
+}
+
+/// Name a usage without its page
+pub(crate) fn usage_name(usage: u32) -> String {
+    let (page, id) = ((usage >> 16) as u16, usage as u16);
+    if page == page::BUTTON {
+        return format!("Button {id}");
+    }
+    match USAGE_NAMES.iter().find(|&&(p, u, _)| p == page && u == id) {
+        Some((_, _, name)) => name.to_string(),
+        None => format!("{id:#04x}"),
+    }
+}
+
+fn collection_name(kind: u32) -> String {
+    match kind {
+        0x00 => "Physical".to_owned(),
+        0x01 => "Application".to_owned(),
+        0x02 => "Logical".to_owned(),
+        0x03 => "Report".to_owned(),
+        0x04 => "Named Array".to_owned(),
+        0x05 => "Usage Switch".to_owned(),
+        0x06 => "Usage Modifier".to_owned(),
+        _ => format!("{kind:#04x}"),
+    }
+}
+
+fn main_flags(value: u32) -> String {
+    let mut names = vec![
+        if value & 1 << 0 != 0 { "Cnst" } else { "Data" },
+        if value & 1 << 1 != 0 { "Var" } else { "Arr" },
+        if value & 1 << 2 != 0 { "Rel" } else { "Abs" },
+    ];
+    let extra = [
+        (1 << 3, "Wrap"),
+        (1 << 4, "NonLin"),
+        (1 << 5, "NoPref"),
+        (1 << 6, "Null"),
+        (1 << 7, "Vol"),
+        (1 << 8, "Buff"),
+    ];
+    names.extend(
+        extra
+            .iter()
+            .filter(|&&(bit, _)| value & bit != 0)
+            .map(|&(_, name)| name),
+    );
+    names.join(",")
+}
+
+fn unit_name(value: u32) -> String {
+    const SYSTEMS: [&str; 4] = ["SILinear", "SIRotation", "EnglishLinear", "EnglishRotation"];
+    // Units of each base quantity, for each system
+    const UNITS: [[&str; 4]; 6] = [
+        ["cm", "rad", "in", "deg"],
+        ["g", "g", "slug", "slug"],
+        ["s", "s", "s", "s"],
+        ["K", "K", "F", "F"],
+        ["A", "A", "A", "A"],
+        ["cd", "cd", "cd", "cd"],
+    ];
+
+    let nibble = |i: u32| sign_extend_nibble((value >> (i * 4)) & 0xf);
+    let system = value & 0xf;
+    if !(1..=4).contains(&system) {
+        return if value == 0 {
+            "None".to_owned()
+        } else {
+            format!("{value:#x}")
+        };
+    }
+
+    let units: Vec<String> = UNITS
+        .iter()
+        .enumerate()
+        .filter_map(|(i, units)| {
+            let unit = units[system as usize - 1];
+            match nibble(i as u32 + 1) {
+                0 => None,
+                1 => Some(unit.to_owned()),
+                n => Some(format!("{unit}^{n}")),
+            }
+        })
+        .collect();
+
+    format!("{}: {}", SYSTEMS[system as usize - 1], units.join(" * "))
+}
+
+fn sign_extend_nibble(value: u32) -> i32 {
+    ((value as i32) << 28) >> 28
+}
+
+impl Item<'_> {
+    /// Describe the item, naming usages with `usage_page`
+    fn describe(&self, usage_page: u16) -> String {
+        let udata = self.udata();
+        let usage = || {
+            let usage = if self.data.len() == 4 {
+                udata
+            } else {
+                (usage_page as u32) << 16 | udata
+            };
+            usage_name(usage)
+        };
+
+        match (self.item_type, self.tag) {
+            (ItemType::Main, 0x8) => format!("Input ({})", main_flags(udata)),
+            (ItemType::Main, 0x9) => format!("Output ({})", main_flags(udata)),
+            (ItemType::Main, 0xb) => format!("Feature ({})", main_flags(udata)),
+            (ItemType::Main, 0xa) => format!("Collection ({})", collection_name(udata)),
+            (ItemType::Main, 0xc) => "End Collection".to_owned(),
+            (ItemType::Global, 0x0) => format!("Usage Page ({})", page_name(udata as u16)),
+            (ItemType::Global, 0x1) => format!("Logical Minimum ({})", self.sdata()),
+            (ItemType::Global, 0x2) => format!("Logical Maximum ({})", self.sdata()),
+            (ItemType::Global, 0x3) => format!("Physical Minimum ({})", self.sdata()),
+            (ItemType::Global, 0x4) => format!("Physical Maximum ({})", self.sdata()),
+            (ItemType::Global, 0x5) => {
+                // Only the low nibble is used by most devices
+                let exponent = if udata & !0xf == 0 {
+                    sign_extend_nibble(udata)
+                } else {
+                    self.sdata()
+                };
+                format!("Unit Exponent ({exponent})")
+            }
+            (ItemType::Global, 0x6) => format!("Unit ({})", unit_name(udata)),
+            (ItemType::Global, 0x7) => format!("Report Size ({udata})"),
+            (ItemType::Global, 0x8) => format!("Report ID ({udata})"),
+            (ItemType::Global, 0x9) => format!("Report Count ({udata})"),
+            (ItemType::Global, 0xa) => "Push".to_owned(),
+            (ItemType::Global, 0xb) => "Pop".to_owned(),
+            (ItemType::Local, 0x0) => format!("Usage ({})", usage()),
+            (ItemType::Local, 0x1) => format!("Usage Minimum ({udata})"),
+            (ItemType::Local, 0x2) => format!("Usage Maximum ({udata})"),
+            (ItemType::Local, 0x3) => format!("Designator Index ({udata})"),
+            (ItemType::Local, 0x4) => format!("Designator Minimum ({udata})"),
+            (ItemType::Local, 0x5) => format!("Designator Maximum ({udata})"),
+            (ItemType::Local, 0x7) => format!("String Index ({udata})"),
+            (ItemType::Local, 0x8) => format!("String Minimum ({udata})"),
+            (ItemType::Local, 0x9) => format!("String Maximum ({udata})"),
+            (ItemType::Local, 0xa) => format!("Delimiter ({udata})"),
+            (item_type, tag) => format!("Unknown ({item_type:?} item, tag {tag:#x})"),
+        }
+    }
+}
+
+impl Display for ReportKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ReportKind::Input => write!(f, "Input"),
+            ReportKind::Output => write!(f, "Output"),
+            ReportKind::Feature => write!(f, "Feature"),
+        }
+    }
+}
+
+impl Display for Field {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let bits = self.size * self.count;
+        write!(f, "{:>4} +{:<3} ", self.offset, bits)?;
+
+        let Some(&first) = self.usages.first() else {
+            return write!(f, "Padding");
+        };
+        let name =
+            |usage: u32| format!("{}.{}", page_name((usage >> 16) as u16), usage_name(usage));
+
+        if self.flags & flags::VARIABLE as u32 != 0 {
+            write!(f, "{}", name(first))?;
+        } else {
+            let last = *self.usages.last().unwrap();
+            write!(f, "{} x {} bits of {}", self.count, self.size, name(first))?;
+            if last != first {
+                write!(f, " .. {}", usage_name(last))?;
+            }
+        }
+
+        write!(f, " [{}, {}]", self.logical_min, self.logical_max)?;
+        if self.flags & flags::RELATIVE as u32 != 0 {
+            write!(f, " relative")?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for Report {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let bytes = self.bits.div_ceil(8);
+        match self.id {
+            0 => writeln!(f, "{} report, {bytes} bytes", self.kind)?,
+            id => writeln!(f, "{} report {id}, {bytes} bytes", self.kind)?,
+        }
+        for field in &self.fields {
+            writeln!(f, "  {field}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Format a descriptor as hex, then one line per item, then report layouts
+///
+/// The first line is in the format hid-decode expects:
+///
+/// ```text
+/// R: {decimal length} {hexdump}
+/// ```
+///
+/// Errors are shown in the output, after the items that could be decoded.
+pub(crate) fn dump(desc: &[u8]) -> String {
+    let mut out = format!("R: {}", desc.len());
+    for b in desc {
+        write!(out, " {b:02x}").unwrap();
+    }
+    out.push_str("\n\n");
+
+    let mut decoder = Decoder::default();
+    for item in Items::new(desc) {
+        let res = item.and_then(|item| {
+            // Closing items are shown at the level of the opening item
+            let closing = matches!(
+                (item.item_type, item.tag),
+                (ItemType::Main, 0xc) | (ItemType::Global, 0xb)
+            );
+            let depth = (decoder.depth + decoder.stack.len()).saturating_sub(closing as usize);
+            let hex: Vec<String> = item.bytes.iter().map(|b| format!("{b:02x}")).collect();
+            let text = item.describe(decoder.globals.usage_page);
+            writeln!(out, "{:<15}// {}{text}", hex.join(" "), "  ".repeat(depth)).unwrap();
+            decoder.item(&item)
+        });
+        if let Err(e) = res {
+            writeln!(out, "// Error: {e}").unwrap();
+            return out;
+        }
+    }
+
+    for report in &decoder.reports {
+        write!(out, "\n{report}").unwrap();
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rdesc::{Builder, Collection, flags::*};
+
+    #[test]
+    fn test_layout() {
+        let desc = Builder::new()
+            .usage_page(page::GENERIC_DESKTOP)
+            .usage(desktop::MOUSE)
+            .collection(Collection::Application, |d| {
+                d.report_id(2)
+                    .usage_page(page::BUTTON)
+                    .usage_minimum(1)
+                    .usage_maximum(3)
+                    .logical_range(0, 1)
+                    .report_size(1)
+                    .report_count(3)
+                    .input(VARIABLE)
+                    .report_count(5)
+                    .input(CONSTANT)
+                    .usage_page(page::GENERIC_DESKTOP)
+                    .usage(desktop::X)
+                    .usage(desktop::Y)
+                    .logical_range(-127, 127)
+                    .report_size(8)
+                    .report_count(2)
+                    .input(VARIABLE | RELATIVE);
+            })
+            .finish();
+
+        let reports = layout(&desc).unwrap();
+        assert_eq!(reports.len(), 1);
+        let report = &reports[0];
+        assert_eq!(
+            (report.kind, report.id, report.bits),
+            (ReportKind::Input, 2, 32)
+        );
+
+        let offsets: Vec<_> = report.fields.iter().map(|f| f.offset).collect();
+        assert_eq!(offsets, [8, 9, 10, 11, 16, 24]);
+        let y = &report.fields[5];
+        assert_eq!(
+            y.usages,
+            [(page::GENERIC_DESKTOP as u32) << 16 | desktop::Y as u32]
+        );
+        assert_eq!((y.logical_min, y.logical_max), (-127, 127));
+
+        assert_eq!(
+            application_usage(&desc).unwrap(),
+            Some((page::GENERIC_DESKTOP as u32) << 16 | desktop::MOUSE as u32)
+        );
+        assert_eq!(application_usage(&desc[..2]).unwrap(), None);
+    }
+
+    #[test]
+    fn test_errors() {
+        assert!(layout(&[0x05]).is_err());
+        assert!(layout(&[0xb4]).is_err());
+        assert!(layout(&[0xc0]).is_err());
+        assert!(
+            dump(&[0x05, 0x01, 0x09])
+                .ends_with("// Error: Descriptor ends in the middle of an item\n")
+        );
+    }
+}
@@ -1,25 +1,70 @@
 use std::{collections::HashMap, fmt::Display};
 
-use minijinja;
+use eyre::{OptionExt, Result, bail, eyre};
 use serde::Serialize;
 
-use eyre::{Context, OptionExt, Result, bail};
+use crate::{
+    devices::Features,
+    rdesc::{
+        Builder, Collection, Data, consumer, desktop, digitizers,
+        flags::{CONSTANT, RELATIVE, VARIABLE},
+        page, unit,
+    },
+    reports::{DIAL_REPORT_ID, PAD_BUTTON_BITS, PAD_REPORT_ID, VENDOR_REPORT_ID},
+};
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Protocol {
+    /// Parameters in string descriptor 100, 8-byte reports with ID 7
+    V1,
+    /// Parameters in string descriptor 200, 12-byte reports with ID 8
+    V2,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub(crate) struct DeviceInfo {
     pub firmware: String,
     pub magic_bytes: Vec<u8>,
+    pub protocol: Protocol,
 }
 
-#[derive(Debug, Clone, Copy, Serialize)]
+#[derive(Debug, Clone, Serialize)]
 pub(crate) struct ParsedDeviceInfo {
+    pub protocol: Protocol,
     pub x_max: u32,
     pub y_max: u32,
     pub pres_max: u16,
     pub resolution: u16,
     pub num_btns: u8,
+    /// Maximum stylus tilt in degrees, in either direction
+    pub tilt_max: Option<u8>,
+    /// Field of unknown meaning before the button count
+    #[allow(dead_code)] // Only shown in debug output
+    #[serde(skip)]
+    pub unknown_1: u16,
+    /// Bytes after the known fields, including decoded ones
+    #[allow(dead_code)] // Only shown in debug output
+    #[serde(skip)]
+    pub tail: Vec<u8>,
+    pub caps: Capabilities,
 }
 
+/// Which parts of the device are present, and so which collections the
+/// descriptor contains
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub(crate) struct Capabilities {
+    pub stylus: bool,
+    pub pad: bool,
+    pub ring: bool,
+    pub dials: u8,
+}
+
+/// Bits of `uclogic_config.collections`
+pub(crate) const COLLECTION_STYLUS: u32 = 1 << 0;
+pub(crate) const COLLECTION_PAD: u32 = 1 << 1;
+pub(crate) const COLLECTION_DIAL: u32 = 1 << 2;
+
 impl DeviceInfo {
     pub(crate) fn from_str(text: &str) -> Result<Self> {
         fn unquote(s: &str) -> Result<&str> {
@@ -28,15 +73,27 @@ impl DeviceInfo {
                 .ok_or_eyre("Incorrectly quoted line")
         }
 
-        let info: HashMap<&str, &str> = text
+        let info: Vec<(&str, &str)> = text
             .lines()
             .filter_map(|l| l.split_once('='))
             .map(|(key, value)| Ok((key, unquote(value)?)))
             .collect::<Result<_>>()?;
 
-        if info.contains_key("HUION_PAD_MODE") {
-            bail!("Unsupported v1 protocol device");
-        }
+        Self::from_properties(info)
+    }
+
+    /// From the `HUION_*` properties huion-switcher sets, other properties
+    /// are ignored
+    pub(crate) fn from_properties<'a>(
+        properties: impl IntoIterator<Item = (&'a str, &'a str)>,
+    ) -> Result<Self> {
+        let info: HashMap<&str, &str> = properties.into_iter().collect();
+
+        let protocol = if info.contains_key("HUION_PAD_MODE") {
+            Protocol::V1
+        } else {
+            Protocol::V2
+        };
 
         Ok(Self {
             firmware: info
@@ -49,10 +106,53 @@ impl DeviceInfo {
                     .copied()
                     .ok_or_eyre("No HUION_MAGIC_BYTES found")?,
             )?,
+            protocol,
         })
     }
 
     pub(crate) fn parse(&self) -> Result<ParsedDeviceInfo> {
+        match self.protocol {
+            Protocol::V1 => self.parse_v1(),
+            Protocol::V2 => self.parse_v2(),
+        }
+    }
+
+    fn parse_v1(&self) -> Result<ParsedDeviceInfo> {
+        if self.magic_bytes.len() < 12 {
+            bail!("Device info too short");
+        }
+
+        if self.magic_bytes[0] as usize != self.magic_bytes.len() {
+            bail!("Device info has incorrect length");
+        }
+
+        if self.magic_bytes[1] != USB_DT_STRING {
+            bail!("Device info is not a string descriptor");
+        }
+
+        let m = &self.magic_bytes;
+
+        Ok(ParsedDeviceInfo {
+            protocol: Protocol::V1,
+            x_max: le(&m[2..][..2]),
+            y_max: le(&m[4..][..2]),
+            pres_max: le(&m[8..][..2]) as _,
+            resolution: le(&m[10..][..2]) as _,
+            // Not reported by the device, all v1 pads have 16 button bits
+            num_btns: V1_NUM_BTNS,
+            tilt_max: None,
+            unknown_1: le(&m[6..][..2]) as _,
+            tail: m[12..].to_vec(),
+            caps: Capabilities {
+                stylus: true,
+                pad: true,
+                ring: false,
+                dials: 0,
+            },
+        })
+    }
+
+    fn parse_v2(&self) -> Result<ParsedDeviceInfo> {
         if self.magic_bytes.len() < 18 {
             bail!("Device info too short");
         }
@@ -61,49 +161,295 @@ impl DeviceInfo {
             bail!("Device info has incorrect length");
         }
 
-        fn le(bytes: &[u8]) -> u32 {
-            debug_assert!(bytes.len() <= 4);
-            bytes
-                .iter()
-                .copied()
-                .rev()
-                .fold(0, |acc, x| (acc << 8) | x as u32)
+        if self.magic_bytes[1] != USB_DT_STRING {
+            bail!("Device info is not a string descriptor");
         }
 
         let m = &self.magic_bytes;
 
+        let x_max = le(&m[2..][..3]);
+        let pres_max = le(&m[8..][..2]);
+
         Ok(ParsedDeviceInfo {
-            x_max: le(&m[2..][..3]),
+            protocol: Protocol::V2,
+            x_max,
             y_max: le(&m[5..][..3]),
-            pres_max: le(&m[8..][..2]) as _,
+            pres_max: pres_max as _,
             resolution: le(&m[10..][..2]) as _,
             num_btns: m[13] as _,
+            tilt_max: Some(m[17]).filter(|&t| t != 0),
+            unknown_1: m[12] as _,
+            tail: m[14..].to_vec(),
+            caps: Capabilities {
+                // Keypads report a 1x1 tablet with no pressure
+                stylus: x_max > 1 && pres_max > 0,
+                pad: m[13] > 0,
+                // Not known from magic bytes, assume everything
+                ring: true,
+                dials: 2,
+            },
         })
     }
 }
 
+const V1_NUM_BTNS: u8 = 16;
+
+const USB_DT_STRING: u8 = 0x03;
+
+fn le(bytes: &[u8]) -> u32 {
+    debug_assert!(bytes.len() <= 4);
+    bytes
+        .iter()
+        .copied()
+        .rev()
+        .fold(0, |acc, x| (acc << 8) | x as u32)
+}
+
+impl Display for Protocol {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Protocol::V1 => write!(f, "v1"),
+            Protocol::V2 => write!(f, "v2"),
+        }
+    }
+}
+
 impl ParsedDeviceInfo {
+    /// Take touch ring and dial presence from a device database entry
+    pub(crate) fn apply_features(&mut self, features: &Features) {
+        self.caps.ring = features.ring;
+        self.caps.dials = features.dials;
+    }
+
+    pub(crate) fn collections(&self) -> u32 {
+        let mut collections = 0;
+        if self.caps.stylus {
+            collections |= COLLECTION_STYLUS;
+        }
+        if self.caps.pad {
+            collections |= COLLECTION_PAD;
+        }
+        if self.caps.ring || self.caps.dials > 0 {
+            collections |= COLLECTION_DIAL;
+        }
+        collections
+    }
+
     pub(crate) fn descriptor(&self) -> Result<Vec<u8>> {
-        let mut env = minijinja::Environment::new();
-        fn bytes(bs: &[u8]) -> String {
-            bs.iter()
-                .map(|b| format!("{b:02x}"))
-                .collect::<Vec<_>>()
-                .join(" ")
-        }
-        env.add_filter("u16", |val: u16| bytes(&val.to_le_bytes()));
-        env.add_filter("u32", |val: u32| bytes(&val.to_le_bytes()));
-        env.add_filter("u8", |val: u8| format!("{val:02x}"));
-        let mut hex_str = env
-            .render_str(include_str!("descriptor.j2"), self)
-            .wrap_err("Descriptor template error")?;
-        hex_str.retain(|x| !x.is_ascii_whitespace());
-        Ok(hex::decode(hex_str).wrap_err("Descriptor template output invalid")?)
+        let mut d = Builder::new();
+        if self.caps.stylus {
+            self.stylus_collection(&mut d)?;
+        }
+        if self.caps.pad {
+            if self.num_btns as u32 > PAD_BUTTON_BITS {
+                bail!(
+                    "Device reports {} buttons, at most {PAD_BUTTON_BITS} are supported",
+                    self.num_btns
+                );
+            }
+            self.pad_collection(&mut d);
+        }
+        if self.caps.ring || self.caps.dials > 0 {
+            self.dial_collection(&mut d);
+        }
+        Ok(d.finish())
+    }
+
+    /// Physical size in thousandths of an inch
+    fn physical_size(&self, max: u32) -> Result<u32> {
+        let size = (max as u64 * 1000)
+            .checked_div(self.resolution as u64)
+            .ok_or_eyre("Device reports a resolution of 0")?;
+        u32::try_from(size).map_err(|_| eyre!("Physical size {size} is too large"))
+    }
+
+    fn stylus_collection(&self, d: &mut Builder) -> Result<()> {
+        let x_phys = self.physical_size(self.x_max)?;
+        let y_phys = self.physical_size(self.y_max)?;
+
+        d.usage_page(page::DIGITIZERS)
+            .usage(digitizers::DIGITIZER)
+            .collection(Collection::Application, |d| {
+                d.report_id(VENDOR_REPORT_ID)
+                    .usage(digitizers::STYLUS)
+                    .collection(Collection::Physical, |d| {
+                        // Stylus buttons
+                        d.logical_range(0, 1)
+                            .report_size(1)
+                            .usage(digitizers::TIP_SWITCH)
+                            .usage(digitizers::BARREL_SWITCH)
+                            .usage(digitizers::SECONDARY_BARREL_SWITCH)
+                            .report_count(3)
+                            .input(VARIABLE)
+                            .report_count(4)
+                            .input(CONSTANT)
+                            .usage(digitizers::IN_RANGE)
+                            .report_count(1)
+                            .input(VARIABLE)
+                            .report_size(24)
+                            .report_count(1);
+
+                        // Stylus position
+                        d.push(|d| {
+                            d.usage_page(page::GENERIC_DESKTOP)
+                                .unit(unit::INCH)
+                                .unit_exponent(-3);
+                            for (usage, max, phys) in [
+                                (desktop::X, self.x_max, x_phys),
+                                (desktop::Y, self.y_max, y_phys),
+                            ] {
+                                d.logical_minimum(Data::U32(0))
+                                    .logical_maximum(Data::U32(max))
+                                    .physical_minimum(Data::U32(0))
+                                    .physical_maximum(Data::U32(phys))
+                                    .usage(usage)
+                                    .input(VARIABLE);
+                            }
+                        });
+
+                        // Stylus pressure
+                        d.logical_minimum(Data::U16(0))
+                            .logical_maximum(Data::U16(self.pres_max))
+                            .usage(digitizers::TIP_PRESSURE)
+                            .report_size(16)
+                            .report_count(1)
+                            .input(VARIABLE)
+                            .report_size(8)
+                            .report_count(2);
+
+                        // Stylus tilt
+                        match self.protocol {
+                            Protocol::V2 => {
+                                let tilt_max = self.tilt_max.unwrap_or(60) as i32;
+                                d.push(|d| {
+                                    d.unit(unit::DEGREE)
+                                        .unit_exponent(0)
+                                        .logical_minimum(-tilt_max)
+                                        .physical_minimum(-tilt_max)
+                                        .logical_maximum(tilt_max)
+                                        .physical_maximum(tilt_max)
+                                        .usage(digitizers::X_TILT)
+                                        .usage(digitizers::Y_TILT)
+                                        .input(VARIABLE);
+                                });
+                            }
+                            Protocol::V1 => {
+                                d.input(CONSTANT);
+                            }
+                        }
+                    });
+            });
+        Ok(())
+    }
+
+    fn pad_collection(&self, d: &mut Builder) {
+        let num_btns = self.num_btns as u32;
+        // Buttons past the first 10 go into a separate game pad collection
+        let first_btns = num_btns.min(10);
+
+        d.usage_page(page::GENERIC_DESKTOP)
+            .usage(desktop::KEYPAD)
+            .collection(Collection::Application, |d| {
+                d.report_id(PAD_REPORT_ID).logical_range(0, 1);
+                fake_tablet(d);
+
+                // Buttons
+                d.usage_page(page::BUTTON)
+                    .usage_minimum(1)
+                    .usage_maximum(first_btns as u16)
+                    .report_count(first_btns)
+                    .report_size(1)
+                    .input(VARIABLE);
+                if num_btns > 10 {
+                    d.usage_page(page::GENERIC_DESKTOP)
+                        .usage(desktop::GAME_PAD)
+                        .collection(Collection::Application, |d| {
+                            d.usage_page(page::BUTTON)
+                                .usage_minimum(1)
+                                .usage_maximum((num_btns - 10) as u16)
+                                .report_count(num_btns - 10)
+                                .input(VARIABLE);
+                        });
+                }
+                d.report_count(PAD_BUTTON_BITS - num_btns).input(CONSTANT);
+            });
     }
+
+    fn dial_collection(&self, d: &mut Builder) {
+        d.usage_page(page::GENERIC_DESKTOP)
+            .usage(desktop::KEYPAD)
+            .collection(Collection::Application, |d| {
+                d.report_id(DIAL_REPORT_ID)
+                    .logical_range(0, 1)
+                    .usage_page(page::DIGITIZERS)
+                    .usage(digitizers::TABLET_FUNCTION_KEYS)
+                    // Fake stuff to be recognized as a tablet
+                    .collection(Collection::Physical, |d| {
+                        d.usage_page(page::BUTTON)
+                            .usage(1)
+                            .report_count(1)
+                            .report_size(1)
+                            .input(VARIABLE)
+                            .report_count(7)
+                            .input(CONSTANT)
+                            .usage_page(page::GENERIC_DESKTOP)
+                            .usage(desktop::X)
+                            .usage(desktop::Y)
+                            .report_size(8)
+                            .report_count(2)
+                            .input(VARIABLE)
+                            .report_count(1)
+                            .input(CONSTANT);
+
+                        // Wheels
+                        d.usage_page(page::GENERIC_DESKTOP)
+                            .usage(desktop::WHEEL)
+                            .report_count(1)
+                            .logical_range(-1, 1)
+                            .input(VARIABLE | RELATIVE);
+                        if self.caps.dials >= 2 {
+                            d.usage_page(page::CONSUMER)
+                                .usage(consumer::AC_PAN)
+                                .report_count(1)
+                                .input(VARIABLE | RELATIVE);
+                        } else {
+                            d.report_count(1).input(CONSTANT);
+                        }
+                    })
+                    .report_size(8)
+                    .report_count(5)
+                    .input(CONSTANT);
+            });
+    }
+}
+
+/// Pen-like fields making the pad collections look like a tablet
+fn fake_tablet(d: &mut Builder) {
+    d.usage_page(page::DIGITIZERS)
+        .usage(digitizers::TABLET_FUNCTION_KEYS)
+        .collection(Collection::Physical, |d| {
+            d.usage(digitizers::BARREL_SWITCH)
+                .report_count(1)
+                .report_size(1)
+                .input(VARIABLE)
+                .report_count(7)
+                .input(CONSTANT)
+                .usage_page(page::GENERIC_DESKTOP)
+                .usage(desktop::X)
+                .usage(desktop::Y)
+                .report_count(2)
+                .report_size(8)
+                .input(VARIABLE);
+        });
 }
 
 impl Display for ParsedDeviceInfo {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if !self.caps.stylus {
+            write!(f, "Device with {} buttons, no stylus", self.num_btns)?;
+            return self.fmt_wheels(f);
+        }
+
         let x_physical = self.x_max as f64 / self.resolution as f64;
         let y_physical = self.y_max as f64 / self.resolution as f64;
 
@@ -117,7 +463,26 @@ impl Display for ParsedDeviceInfo {
             self.resolution,
             x_physical,
             y_physical,
-        )
+        )?;
+
+        if let Some(tilt_max) = self.tilt_max {
+            write!(f, ", max tilt {tilt_max} degrees")?;
+        }
+
+        self.fmt_wheels(f)
+    }
+}
+
+impl ParsedDeviceInfo {
+    fn fmt_wheels(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.caps.ring {
+            write!(f, ", touch ring")?;
+        }
+        match self.caps.dials {
+            0 => Ok(()),
+            1 => write!(f, ", 1 dial"),
+            n => write!(f, ", {n} dials"),
+        }
     }
 }
 
@@ -125,36 +490,28 @@ impl Display for ParsedDeviceInfo {
 mod tests {
     use super::*;
 
-    /// Format descriptor hexdump into what hid-decode expects
-    ///
-    /// ```text
-    /// R: {decimal length} {hexdump}
-    /// ```
-    ///
-    /// For example
-    ///
-    /// ```text
-    /// R: 5 aa bb 00 12 34
-    /// ```
     fn descriptor_dump(bytes: &[u8]) -> String {
-        let byte_hex = |b: u8| -> [u8; 3] {
-            const HEX: [u8; 16] = *b"0123456789abcdef";
-            [b' ', HEX[(b >> 4) as usize], HEX[(b & 0xf) as usize]]
-        };
-        let hexdump: Vec<u8> = bytes.iter().copied().flat_map(byte_hex).collect();
-        let hexdump = String::from_utf8(hexdump).unwrap();
-
-        format!("R: {}{}", bytes.len(), hexdump)
+        crate::decode::dump(bytes)
     }
 
     #[test]
     fn test_desc() {
         let dev = ParsedDeviceInfo {
+            protocol: Protocol::V2,
             x_max: 50800,
             y_max: 31750,
             pres_max: 8191,
             resolution: 5080,
             num_btns: 13,
+            tilt_max: Some(60),
+            unknown_1: 3,
+            tail: vec![],
+            caps: Capabilities {
+                stylus: true,
+                pad: true,
+                ring: true,
+                dials: 2,
+            },
         };
 
         let desc = dev.descriptor().unwrap();
@@ -165,12 +522,178 @@ mod tests {
     #[test]
     fn test_less_buttons_desc() {
         let dev = ParsedDeviceInfo {
+            protocol: Protocol::V2,
             x_max: 50800,
             y_max: 31750,
             pres_max: 8191,
             resolution: 5080,
             num_btns: 5,
+            tilt_max: Some(60),
+            unknown_1: 3,
+            tail: vec![],
+            caps: Capabilities {
+                stylus: true,
+                pad: true,
+                ring: true,
+                dials: 2,
+            },
+        };
+
+        let desc = dev.descriptor().unwrap();
+        insta::assert_snapshot!(descriptor_dump(&desc));
+    }
+
+    #[test]
+    fn test_tilt_max_desc() {
+        let text = include_str!("../fixtures/device-info/gaomon-m7.txt");
+        let mut dev = DeviceInfo::from_str(text).unwrap().parse().unwrap();
+        dev.tilt_max = Some(45);
+
+        // Logical and physical minimum and maximum
+        let tilt = [0x15, (-45i8) as u8, 0x35, (-45i8) as u8, 0x25, 45, 0x45, 45];
+        let desc = dev.descriptor().unwrap();
+        assert!(desc.windows(tilt.len()).any(|w| w == tilt));
+    }
+
+    #[test]
+    fn test_bad_resolution_desc() {
+        let mut dev = ParsedDeviceInfo {
+            protocol: Protocol::V1,
+            x_max: 40000,
+            y_max: 25000,
+            pres_max: 2047,
+            resolution: 0,
+            num_btns: 12,
+            tilt_max: None,
+            unknown_1: 3,
+            tail: vec![],
+            caps: Capabilities {
+                stylus: true,
+                pad: true,
+                ring: false,
+                dials: 0,
+            },
         };
+        let err = dev.descriptor().unwrap_err();
+        assert_eq!(format!("{err}"), "Device reports a resolution of 0");
+
+        // The v2 maximum is 24 bits, too large at this resolution
+        dev.x_max = 0xff_ffff;
+        dev.resolution = 1;
+        assert!(dev.descriptor().is_err());
+    }
+
+    #[test]
+    fn test_too_many_buttons_desc() {
+        let mut dev = DeviceInfo::from_str(FIXTURES[0].1)
+            .unwrap()
+            .parse()
+            .unwrap();
+        dev.num_btns = 65;
+        let err = dev.descriptor().unwrap_err();
+        assert_eq!(
+            format!("{err}"),
+            "Device reports 65 buttons, at most 64 are supported"
+        );
+    }
+
+    #[test]
+    fn test_v1_desc() {
+        // Parameters of a Huion H610 Pro
+        let info = DeviceInfo::from_str(
+            "HUION_FIRMWARE_ID=\"HUION_M182\"\n\
+             HUION_MAGIC_BYTES=\"0c03409ca8610300ff07a00f\"\n\
+             HUION_PAD_MODE=\"1\"\n",
+        )
+        .unwrap();
+
+        let dev = info.parse().unwrap();
+        assert_eq!(dev.protocol, Protocol::V1);
+        assert_eq!((dev.x_max, dev.y_max), (40000, 25000));
+        assert_eq!((dev.pres_max, dev.resolution), (2047, 4000));
+
+        let desc = dev.descriptor().unwrap();
+        insta::assert_snapshot!(descriptor_dump(&desc));
+    }
+
+    /// Device info of known devices, in the format of `--device-info`
+    static FIXTURES: &[(&str, &str)] = &[
+        (
+            "gaomon-m7",
+            include_str!("../fixtures/device-info/gaomon-m7.txt"),
+        ),
+        (
+            "huion-h610-pro",
+            include_str!("../fixtures/device-info/huion-h610-pro.txt"),
+        ),
+        (
+            "huion-keydial-kd20",
+            include_str!("../fixtures/device-info/huion-keydial-kd20.txt"),
+        ),
+    ];
+
+    #[test]
+    fn test_fixtures() {
+        for (name, text) in FIXTURES {
+            let parsed = DeviceInfo::from_str(text).unwrap().parse().unwrap();
+            insta::assert_debug_snapshot!(format!("fixture_{name}"), parsed);
+        }
+    }
+
+    /// Every fixture, with the features of every built-in device, must
+    /// match the reports of the BPF program
+    #[test]
+    fn test_report_layouts() {
+        let db = crate::devices::DeviceDb::load_from(&[]).unwrap();
+        let features: Vec<Features> = std::iter::once(Features::default())
+            .chain(db.entries().map(|e| e.features.clone()))
+            .collect();
+
+        for (name, text) in FIXTURES {
+            let parsed = DeviceInfo::from_str(text).unwrap().parse().unwrap();
+            crate::reports::check(&parsed.descriptor().unwrap())
+                .unwrap_or_else(|e| panic!("{name}: {e:#}"));
+
+            for f in &features {
+                let mut dev = parsed.clone();
+                dev.apply_features(f);
+                crate::reports::check(&dev.descriptor().unwrap())
+                    .unwrap_or_else(|e| panic!("{name} with {f}: {e:#}"));
+            }
+        }
+
+        for num_btns in 1..=64 {
+            let mut dev = DeviceInfo::from_str(FIXTURES[0].1)
+                .unwrap()
+                .parse()
+                .unwrap();
+            dev.num_btns = num_btns;
+            crate::reports::check(&dev.descriptor().unwrap())
+                .unwrap_or_else(|e| panic!("{num_btns} buttons: {e:#}"));
+        }
+    }
+
+    #[test]
+    fn test_keypad_desc() {
+        let text = include_str!("../fixtures/device-info/huion-keydial-kd20.txt");
+        let mut dev = DeviceInfo::from_str(text).unwrap().parse().unwrap();
+        dev.apply_features(&Features {
+            ring: false,
+            dials: 1,
+            battery: false,
+        });
+        assert_eq!(dev.collections(), COLLECTION_PAD | COLLECTION_DIAL);
+
+        let desc = dev.descriptor().unwrap();
+        insta::assert_snapshot!(descriptor_dump(&desc));
+    }
+
+    #[test]
+    fn test_no_wheel_desc() {
+        let text = include_str!("../fixtures/device-info/gaomon-m7.txt");
+        let mut dev = DeviceInfo::from_str(text).unwrap().parse().unwrap();
+        dev.apply_features(&Features::default());
+        assert_eq!(dev.collections(), COLLECTION_STYLUS | COLLECTION_PAD);
 
         let desc = dev.descriptor().unwrap();
         insta::assert_snapshot!(descriptor_dump(&desc));
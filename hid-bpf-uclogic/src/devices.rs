@@ -0,0 +1,308 @@
+use std::{
+    fmt::Display,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use eyre::{Context, Result, eyre};
+
+use crate::hid::{Bus, HidId};
+
+/// Entries shipped with hid-bpf-uclogic, used unless overridden
+static BUILTIN: &[(&str, &str)] = &[
+    ("gaomon-m7", include_str!("../devices/gaomon-m7.toml")),
+    ("huion-hc16", include_str!("../devices/huion-hc16.toml")),
+    (
+        "huion-inspiroy-dial-2",
+        include_str!("../devices/huion-inspiroy-dial-2.toml"),
+    ),
+];
+
+/// System directories, in increasing order of precedence
+static SYSTEM_DIRS: &[&str] = &[
+    "/usr/share/hid-bpf-uclogic/devices",
+    "/etc/hid-bpf-uclogic/devices",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[serde(try_from = "String")]
+pub(crate) struct UsbId {
+    pub vid: u16,
+    pub pid: u16,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Features {
+    #[serde(default)]
+    pub ring: bool,
+    #[serde(default)]
+    pub dials: u8,
+    #[serde(default)]
+    pub battery: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum Quirk {
+    /// Leave the keyboard and mouse compatibility interfaces bound
+    KeepCompatInterfaces,
+    /// Quirks from newer versions are ignored
+    #[serde(other)]
+    Unknown,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct DeviceEntry {
+    pub name: String,
+    #[serde(default)]
+    pub usb: Vec<UsbId>,
+    /// Bluetooth and virtual devices, which have ids of their own, as in
+    /// `HID_ID` like `0005:256c:8251`
+    #[serde(default)]
+    pub hid: Vec<HidId>,
+    /// Firmware IDs, `*` matches any sequence of characters
+    pub firmware: Vec<String>,
+    #[serde(default)]
+    pub features: Features,
+    #[serde(default)]
+    pub quirks: Vec<Quirk>,
+}
+
+/// Supported devices, one entry per file
+///
+/// Each entry is identified by its file name without `.toml`, and files in
+/// later directories replace entries with the same name.
+#[derive(Debug, Default)]
+pub(crate) struct DeviceDb {
+    entries: Vec<(String, DeviceEntry)>,
+}
+
+impl TryFrom<String> for UsbId {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        let parse = |s: &str| u16::from_str_radix(s, 16).ok().filter(|_| s.len() == 4);
+        value
+            .split_once(':')
+            .and_then(|(vid, pid)| {
+                Some(Self {
+                    vid: parse(vid)?,
+                    pid: parse(pid)?,
+                })
+            })
+            .ok_or_else(|| format!("Invalid USB id {value:?}, expected vvvv:pppp"))
+    }
+}
+
+impl Display for UsbId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:04x}:{:04x}", self.vid, self.pid)
+    }
+}
+
+impl Display for Features {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut features = Vec::new();
+        if self.ring {
+            features.push("touch ring".to_owned());
+        }
+        match self.dials {
+            0 => (),
+            1 => features.push("1 dial".to_owned()),
+            n => features.push(format!("{n} dials")),
+        }
+        if self.battery {
+            features.push("battery".to_owned());
+        }
+        if features.is_empty() {
+            write!(f, "no extra features")
+        } else {
+            write!(f, "{}", features.join(", "))
+        }
+    }
+}
+
+impl DeviceEntry {
+    /// USB devices match by USB id, others by HID id
+    pub(crate) fn matches_id(&self, id: HidId) -> bool {
+        match id.bus {
+            Bus::Usb => self.usb.contains(&id.id),
+            _ => self.hid.contains(&id),
+        }
+    }
+
+    pub(crate) fn matches_firmware(&self, firmware: &str) -> bool {
+        self.firmware.iter().any(|p| glob_match(p, firmware))
+    }
+
+    pub(crate) fn has_quirk(&self, quirk: Quirk) -> bool {
+        self.quirks.contains(&quirk)
+    }
+}
+
+impl DeviceDb {
+    /// Load built-in entries, then system directories, then the user directory
+    pub(crate) fn load() -> Result<Self> {
+        let mut dirs: Vec<PathBuf> = SYSTEM_DIRS.iter().map(PathBuf::from).collect();
+        dirs.extend(user_dir());
+        Self::load_from(&dirs)
+    }
+
+    pub(crate) fn load_from(dirs: &[PathBuf]) -> Result<Self> {
+        let mut db = Self::default();
+
+        for (name, text) in BUILTIN {
+            db.insert(name.to_string(), parse_entry(text)?);
+        }
+
+        for dir in dirs {
+            db.load_dir(dir)
+                .wrap_err_with(|| format!("Loading devices from {}", dir.display()))?;
+        }
+
+        Ok(db)
+    }
+
+    fn load_dir(&mut self, dir: &Path) -> Result<()> {
+        let read_dir = match std::fs::read_dir(dir) {
+            Ok(read_dir) => read_dir,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => Err(e)?,
+        };
+
+        let mut paths: Vec<PathBuf> = read_dir
+            .map(|ent| Ok(ent?.path()))
+            .collect::<std::io::Result<_>>()?;
+        paths.retain(|p| p.extension().is_some_and(|e| e == "toml"));
+        paths.sort();
+
+        for path in paths {
+            let name = path.file_stem().unwrap().to_string_lossy().into_owned();
+            let text = std::fs::read_to_string(&path)?;
+            let entry =
+                parse_entry(&text).wrap_err_with(|| format!("Parsing {}", path.display()))?;
+            self.insert(name, entry);
+        }
+
+        Ok(())
+    }
+
+    fn insert(&mut self, name: String, entry: DeviceEntry) {
+        match self.entries.iter_mut().find(|(n, _)| *n == name) {
+            Some((_, e)) => *e = entry,
+            None => self.entries.push((name, entry)),
+        }
+    }
+
+    pub(crate) fn entries(&self) -> impl Iterator<Item = &DeviceEntry> {
+        self.entries.iter().map(|(_, e)| e)
+    }
+
+    pub(crate) fn by_id(&self, id: HidId) -> impl Iterator<Item = &DeviceEntry> {
+        self.entries().filter(move |e| e.matches_id(id))
+    }
+
+    /// Find the entry for a firmware ID, limited to a device id if known
+    pub(crate) fn by_firmware(&self, id: Option<HidId>, firmware: &str) -> Option<&DeviceEntry> {
+        self.entries()
+            .filter(|e| id.is_none_or(|id| e.matches_id(id)))
+            .find(|e| e.matches_firmware(firmware))
+    }
+}
+
+fn parse_entry(text: &str) -> Result<DeviceEntry> {
+    toml::from_str(text).map_err(|e| eyre!("{e}"))
+}
+
+fn user_dir() -> Option<PathBuf> {
+    Some(config_dir()?.join("devices"))
+}
+
+/// `~/.config/hid-bpf-uclogic`, or under `$XDG_CONFIG_HOME`
+pub(crate) fn config_dir() -> Option<PathBuf> {
+    let config = std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|d| !d.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| {
+            let home = std::env::var_os("HOME")?;
+            Some(PathBuf::from(home).join(".config"))
+        })?;
+    Some(config.join("hid-bpf-uclogic"))
+}
+
+pub(crate) fn glob_match(pattern: &str, s: &str) -> bool {
+    match pattern.split_once('*') {
+        None => pattern == s,
+        Some((prefix, rest)) => {
+            let Some(s) = s.strip_prefix(prefix) else {
+                return false;
+            };
+            (0..=s.len())
+                .filter(|&i| s.is_char_boundary(i))
+                .any(|i| glob_match(rest, &s[i..]))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_builtin() {
+        let db = DeviceDb::load_from(&[]).unwrap();
+        let m7 = HidId::usb(UsbId {
+            vid: 0x256c,
+            pid: 0x0064,
+        });
+
+        assert_eq!(db.by_id(m7).count(), 2);
+        let bluetooth = HidId {
+            bus: Bus::Bluetooth,
+            ..m7
+        };
+        assert_eq!(db.by_id(bluetooth).count(), 0);
+        let entry = db.by_firmware(Some(m7), "HUION_T18C_211220").unwrap();
+        assert_eq!(entry.name, "Huion HC16");
+        assert!(entry.features.ring);
+        assert!(db.by_firmware(Some(m7), "HUION_T216_220819").is_none());
+    }
+
+    #[test]
+    fn test_override() {
+        let dir = std::env::temp_dir().join(format!("hid-bpf-uclogic-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("gaomon-m7.toml"),
+            r#"
+            name = "Gaomon M7"
+            usb = ["256c:0064"]
+            hid = ["0005:256c:8251"]
+            firmware = ["GM001_T207_*"]
+            quirks = ["keep-compat-interfaces", "from-the-future"]
+            "#,
+        )
+        .unwrap();
+
+        let db = DeviceDb::load_from(std::slice::from_ref(&dir));
+        std::fs::remove_dir_all(&dir).unwrap();
+        let db = db.unwrap();
+
+        assert_eq!(db.entries().count(), BUILTIN.len());
+        let entry = db.by_firmware(None, "GM001_T207_230101").unwrap();
+        assert!(entry.has_quirk(Quirk::KeepCompatInterfaces));
+        let bluetooth = HidId::parse("0005:0000256C:00008251").unwrap();
+        assert_eq!(db.by_id(bluetooth).count(), 1);
+    }
+
+    #[test]
+    fn test_glob() {
+        assert!(glob_match("HUION_T216_*", "HUION_T216_220819"));
+        assert!(glob_match("*_T216_*", "HUION_T216_220819"));
+        assert!(!glob_match("HUION_T216_*", "HUION_T21h_230511"));
+        assert!(glob_match("GM001_T207_210524", "GM001_T207_210524"));
+    }
+}
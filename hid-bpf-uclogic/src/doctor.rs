@@ -0,0 +1,281 @@
+//! Checks for what loading the driver needs, for `doctor`
+//!
+//! A failed load only shows the first error from libbpf or the kernel, so
+//! each prerequisite is checked on its own here, with a hint for fixing it.
+
+use std::path::{Path, PathBuf};
+
+use eyre::{Result, bail, eyre};
+use libbpf_rs::btf::{Btf, types};
+
+use crate::{devices::DeviceDb, hid::HidId, sysfs::SysfsPath};
+
+const CAP_SYS_ADMIN: u32 = 21;
+const CAP_BPF: u32 = 39;
+
+/// Where udev rules look for programs, after `PATH`
+const UDEV_DIRS: &[&str] = &["/usr/lib/udev", "/lib/udev", "/etc/udev"];
+
+pub(crate) struct Check {
+    pub what: &'static str,
+    /// Failures of optional checks are only warnings
+    pub required: bool,
+    pub result: Result<()>,
+    pub hint: &'static str,
+}
+
+impl Check {
+    fn required(what: &'static str, result: Result<()>, hint: &'static str) -> Self {
+        Self {
+            what,
+            required: true,
+            result,
+            hint,
+        }
+    }
+}
+
+/// Run all checks
+pub(crate) fn checks(sysfs: &SysfsPath, db: &DeviceDb, huion_switcher: &Path) -> Vec<Check> {
+    let mut checks = Vec::new();
+
+    let btf = Btf::from_vmlinux();
+    let btf_err = || eyre!("No kernel BTF");
+    checks.push(Check::required(
+        "Kernel BTF",
+        btf.as_ref().map(|_| ()).map_err(|e| eyre!("{e}")),
+        "The kernel needs CONFIG_DEBUG_INFO_BTF=y",
+    ));
+    checks.push(Check::required(
+        "struct hid_bpf_ops in kernel BTF",
+        match &btf {
+            Ok(btf) => has_type::<types::Struct>(btf, "hid_bpf_ops"),
+            Err(_) => Err(btf_err()),
+        },
+        "The kernel needs to be Linux 6.11 or later, with CONFIG_HID_BPF=y",
+    ));
+    checks.push(Check::required(
+        "hid_bpf_get_data kfunc in kernel BTF",
+        match &btf {
+            Ok(btf) => has_type::<types::Func>(btf, "hid_bpf_get_data"),
+            Err(_) => Err(btf_err()),
+        },
+        "The kernel needs CONFIG_HID_BPF=y",
+    ));
+
+    checks.push(Check::required(
+        "bpffs mounted at /sys/fs/bpf",
+        crate::find_bpffs().map(|_| ()),
+        "To mount it at boot, add to /etc/fstab: bpffs /sys/fs/bpf bpf defaults 0 0",
+    ));
+    checks.push(Check::required(
+        "CAP_BPF or CAP_SYS_ADMIN",
+        check_capabilities(),
+        "Run as root",
+    ));
+    checks.push(check_hid_uclogic(sysfs, db));
+    checks.push(Check {
+        what: "huion-switcher",
+        required: false,
+        result: find_program(huion_switcher).map(|_| ()),
+        hint: "Only needed for --with-huion-switcher and install-udev, see https://github.com/whot/huion-switcher",
+    });
+
+    checks
+}
+
+/// Print a checklist, and fail if any required check failed
+pub(crate) fn report(checks: &[Check]) -> Result<()> {
+    for check in checks {
+        match &check.result {
+            Ok(()) => println!("  ok    {}", check.what),
+            Err(e) => {
+                let status = if check.required { "FAIL" } else { "warn" };
+                println!("  {status}  {}: {e:#}", check.what);
+                println!("        {}", check.hint);
+            }
+        }
+    }
+
+    let failed = checks
+        .iter()
+        .filter(|c| c.required && c.result.is_err())
+        .count();
+    if failed > 0 {
+        bail!("{failed} of {} checks failed", checks.len());
+    }
+    Ok(())
+}
+
+fn has_type<'a, K>(btf: &'a Btf, name: &str) -> Result<()>
+where
+    K: TryFrom<libbpf_rs::btf::BtfType<'a>>,
+{
+    match btf.type_by_name::<K>(name) {
+        Some(_) => Ok(()),
+        None => bail!("Not found"),
+    }
+}
+
+fn check_capabilities() -> Result<()> {
+    let status = std::fs::read_to_string("/proc/self/status")?;
+    let caps = effective_capabilities(&status).ok_or_else(|| eyre!("No CapEff in status"))?;
+    if caps & (1 << CAP_BPF | 1 << CAP_SYS_ADMIN) == 0 {
+        bail!("Neither capability is effective");
+    }
+    Ok(())
+}
+
+/// `CapEff` from `/proc/self/status`
+fn effective_capabilities(status: &str) -> Option<u64> {
+    let caps = status.lines().find_map(|l| l.strip_prefix("CapEff:"))?;
+    u64::from_str_radix(caps.trim(), 16).ok()
+}
+
+/// Fails only if hid-uclogic has a supported device, being loaded for other
+/// devices is only a warning
+fn check_hid_uclogic(sysfs: &SysfsPath, db: &DeviceDb) -> Check {
+    let (required, result) = match hid_uclogic_devices(sysfs) {
+        Ok(None) => (true, Ok(())),
+        Ok(Some(bound)) => {
+            let supported = |name: &String| {
+                let id = name.split('.').next().and_then(HidId::parse);
+                id.is_some_and(|id| db.by_id(id).next().is_some())
+            };
+            let (supported, others): (Vec<_>, Vec<_>) = bound.into_iter().partition(supported);
+            if !supported.is_empty() {
+                (true, Err(eyre!("Bound to {}", supported.join(", "))))
+            } else if !others.is_empty() {
+                let others = others.join(", ");
+                (false, Err(eyre!("Loaded, bound to unsupported {others}")))
+            } else {
+                (false, Err(eyre!("Loaded, but not bound to any device")))
+            }
+        }
+        Err(e) => (true, Err(e)),
+    };
+
+    Check {
+        what: "hid-uclogic not bound to supported devices",
+        required,
+        result,
+        hint: "Unload it with: modprobe -r hid-uclogic, and blacklist it, see modprobe.d(5). If it is built into the kernel, unbind each device with: echo <device> > /sys/bus/hid/drivers/hid-uclogic/unbind",
+    }
+}
+
+/// Names of the HID devices bound to hid-uclogic, if it is loaded
+fn hid_uclogic_devices(sysfs: &SysfsPath) -> Result<Option<Vec<String>>> {
+    let Some(hid_bus) = sysfs.get_subsystem("hid")? else {
+        return Ok(None);
+    };
+    if hid_bus.sub("drivers/hid-uclogic")?.is_none() {
+        return Ok(None);
+    }
+
+    // Bound devices are links named after them, like 0003:256C:0064.004C
+    let bound = hid_bus
+        .list("drivers/hid-uclogic")?
+        .into_iter()
+        .map(|name| name.to_string_lossy().into_owned())
+        .filter(|name| name.contains(':'))
+        .collect();
+    Ok(Some(bound))
+}
+
+/// Like the shell would, but also looking where udev rules do
+fn find_program(program: &Path) -> Result<PathBuf> {
+    if program.components().count() > 1 {
+        return if is_executable(program) {
+            Ok(program.to_owned())
+        } else {
+            bail!("{} not found", program.display())
+        };
+    }
+
+    let path = std::env::var_os("PATH").unwrap_or_default();
+    let dirs = std::env::split_paths(&path).chain(UDEV_DIRS.iter().map(PathBuf::from));
+    for dir in dirs {
+        let candidate = dir.join(program);
+        if is_executable(&candidate) {
+            return Ok(candidate);
+        }
+    }
+    bail!(
+        "{} not found in PATH or {}",
+        program.display(),
+        UDEV_DIRS.join(", ")
+    );
+}
+
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+
+    std::fs::metadata(path).is_ok_and(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixture::{FakeSysfs, UsbDevice, VENDOR_RDESC};
+
+    #[test]
+    fn test_effective_capabilities() {
+        let status = "Name:\tcat\nCapInh:\t0000000000000000\nCapEff:\t000001ffffffffff\n";
+        assert_eq!(effective_capabilities(status), Some(0x1ff_ffff_ffff));
+        assert_eq!(effective_capabilities("Name:\tcat\n"), None);
+    }
+
+    #[test]
+    fn test_hid_uclogic() {
+        let fake = FakeSysfs::new("doctor");
+        fake.usb("1-4", UsbDevice::GAOMON_M7);
+        let other = UsbDevice {
+            vid: "5543",
+            pid: "0081",
+            hid_id: 0x51,
+            interfaces: &[(0, VENDOR_RDESC)],
+            ..UsbDevice::GAOMON_M7
+        };
+        fake.usb("1-5", other);
+        let db = DeviceDb::load_from(&[]).unwrap();
+        let check = || {
+            let sysfs = fake.sysfs();
+            let sysfs = sysfs.root().unwrap();
+            let check = check_hid_uclogic(&sysfs, &db);
+            (check.required, check.result.map_err(|e| format!("{e:#}")))
+        };
+
+        assert_eq!(check(), (true, Ok(())));
+
+        let driver = fake.root.join("bus/hid/drivers/hid-uclogic");
+        std::fs::create_dir_all(&driver).unwrap();
+        std::fs::write(driver.join("bind"), "").unwrap();
+        assert_eq!(
+            check(),
+            (false, Err("Loaded, but not bound to any device".to_owned()))
+        );
+
+        let hid = fake.path("1-5").join("1-5:1.0/0003:5543:0081.0051");
+        std::os::unix::fs::symlink(hid, driver.join("0003:5543:0081.0051")).unwrap();
+        assert_eq!(
+            check(),
+            (
+                false,
+                Err("Loaded, bound to unsupported 0003:5543:0081.0051".to_owned())
+            )
+        );
+
+        let hid = fake.path("1-4").join("1-4:1.0/0003:256C:0064.004C");
+        std::os::unix::fs::symlink(hid, driver.join("0003:256C:0064.004C")).unwrap();
+        assert_eq!(
+            check(),
+            (true, Err("Bound to 0003:256C:0064.004C".to_owned()))
+        );
+    }
+
+    #[test]
+    fn test_find_program() {
+        assert!(find_program(Path::new("/bin/sh")).is_ok());
+        assert!(find_program(Path::new("/nonexistent/huion-switcher")).is_err());
+    }
+}
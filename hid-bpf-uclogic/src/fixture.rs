@@ -0,0 +1,300 @@
+//! Fake sysfs trees for tests
+//!
+//! Only what hid-bpf-uclogic looks at is created: device directories with
+//! their properties, `subsystem` and `driver` links, and the
+//! `bus/*/devices` links to them.
+
+use std::{
+    cell::Cell,
+    path::{Path, PathBuf},
+};
+
+use crate::sysfs::Sysfs;
+
+/// The xHCI root hub, under which tablets are plugged in
+pub(crate) const USB: &str = "devices/pci0000:00/0000:00:14.0/usb1";
+
+/// A sysfs tree in a temporary directory, removed on drop
+pub(crate) struct FakeSysfs {
+    pub root: PathBuf,
+    /// Number of the next hidraw and input devices
+    next_minor: Cell<u32>,
+}
+
+/// A USB device to add, see [`FakeSysfs::usb`]
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct UsbDevice<'a> {
+    pub vid: &'a str,
+    pub pid: &'a str,
+    pub manufacturer: &'a str,
+    pub product: &'a str,
+    /// HID id of the first interface, the others following in order
+    pub hid_id: u16,
+    /// Numbers and report descriptors of the HID interfaces
+    pub interfaces: &'a [(u8, &'a [u8])],
+}
+
+// Descriptors below are written from the kernel hid-uclogic driver and the
+// published layouts of these tablets, not captured from devices. They keep
+// the application collections and report ids, which is what is looked at.
+
+/// Vendor page 0xff00, report 8, as on v2 devices before loading
+pub(crate) const VENDOR_RDESC: &[u8] = &[
+    0x06, 0x00, 0xff, 0x09, 0x01, 0xa1, 0x01, 0x85, 0x08, 0x09, 0x01, 0x15, 0x00, 0x26, 0xff, 0x00,
+    0x75, 0x08, 0x95, 0x0b, 0x81, 0x02, 0xc0,
+];
+
+/// Pen with tip, barrel and in-range bits, X, Y and pressure, as the
+/// compatibility pen interface of v2 devices reports with id 10
+pub(crate) const PEN_RDESC: &[u8] = &[
+    0x05, 0x0d, 0x09, 0x02, 0xa1, 0x01, 0x85, 0x0a, 0x09, 0x20, 0xa1, 0x00, 0x09, 0x42, 0x09, 0x44,
+    0x09, 0x45, 0x15, 0x00, 0x25, 0x01, 0x75, 0x01, 0x95, 0x03, 0x81, 0x02, 0x95, 0x02, 0x81, 0x03,
+    0x09, 0x32, 0x95, 0x01, 0x81, 0x02, 0x95, 0x02, 0x81, 0x03, 0x05, 0x01, 0x09, 0x30, 0x09, 0x31,
+    0x26, 0xff, 0x7f, 0x75, 0x10, 0x95, 0x02, 0x81, 0x02, 0x05, 0x0d, 0x09, 0x30, 0x26, 0xff, 0x07,
+    0x95, 0x01, 0x81, 0x02, 0xc0, 0xc0,
+];
+
+/// The same pen with report id 7, as v1 devices report on interface 0
+pub(crate) const V1_PEN_RDESC: &[u8] = &[
+    0x05, 0x0d, 0x09, 0x02, 0xa1, 0x01, 0x85, 0x07, 0x09, 0x20, 0xa1, 0x00, 0x09, 0x42, 0x09, 0x44,
+    0x09, 0x45, 0x15, 0x00, 0x25, 0x01, 0x75, 0x01, 0x95, 0x03, 0x81, 0x02, 0x95, 0x02, 0x81, 0x03,
+    0x09, 0x32, 0x95, 0x01, 0x81, 0x02, 0x95, 0x02, 0x81, 0x03, 0x05, 0x01, 0x09, 0x30, 0x09, 0x31,
+    0x26, 0xff, 0x7f, 0x75, 0x10, 0x95, 0x02, 0x81, 0x02, 0x05, 0x0d, 0x09, 0x30, 0x26, 0xff, 0x07,
+    0x95, 0x01, 0x81, 0x02, 0xc0, 0xc0,
+];
+
+/// Boot keyboard, which pad buttons are mapped to before loading
+pub(crate) const KEYBOARD_RDESC: &[u8] = &[
+    0x05, 0x01, 0x09, 0x06, 0xa1, 0x01, 0x05, 0x07, 0x19, 0xe0, 0x29, 0xe7, 0x15, 0x00, 0x25, 0x01,
+    0x75, 0x01, 0x95, 0x08, 0x81, 0x02, 0x95, 0x01, 0x75, 0x08, 0x81, 0x01, 0x95, 0x06, 0x75, 0x08,
+    0x15, 0x00, 0x26, 0xff, 0x00, 0x19, 0x00, 0x2a, 0xff, 0x00, 0x81, 0x00, 0xc0,
+];
+
+/// Three buttons, X, Y and a wheel, which rings and dials scroll before
+/// loading
+pub(crate) const MOUSE_RDESC: &[u8] = &[
+    0x05, 0x01, 0x09, 0x02, 0xa1, 0x01, 0x09, 0x01, 0xa1, 0x00, 0x05, 0x09, 0x19, 0x01, 0x29, 0x03,
+    0x15, 0x00, 0x25, 0x01, 0x95, 0x03, 0x75, 0x01, 0x81, 0x02, 0x95, 0x01, 0x75, 0x05, 0x81, 0x01,
+    0x05, 0x01, 0x09, 0x30, 0x09, 0x31, 0x09, 0x38, 0x15, 0x81, 0x25, 0x7f, 0x75, 0x08, 0x95, 0x03,
+    0x81, 0x06, 0xc0, 0xc0,
+];
+
+impl UsbDevice<'_> {
+    /// v2, with pen and keyboard compatibility interfaces
+    pub(crate) const GAOMON_M7: Self = Self {
+        vid: "256c",
+        pid: "0064",
+        manufacturer: "GAOMON",
+        product: "Gaomon Tablet_M7",
+        hid_id: 0x4c,
+        interfaces: &[(0, VENDOR_RDESC), (1, PEN_RDESC), (2, KEYBOARD_RDESC)],
+    };
+
+    /// v2, with keyboard and mouse compatibility interfaces, the mouse
+    /// scrolling with the ring
+    pub(crate) const HUION_HC16: Self = Self {
+        vid: "256c",
+        pid: "0064",
+        manufacturer: "HUION",
+        product: "Huion Tablet_HC16",
+        hid_id: 0x52,
+        interfaces: &[(0, VENDOR_RDESC), (1, KEYBOARD_RDESC), (2, MOUSE_RDESC)],
+    };
+
+    /// v2, with an extra mouse interface for the dials
+    pub(crate) const HUION_DIAL_2: Self = Self {
+        vid: "256c",
+        pid: "0060",
+        manufacturer: "HUION",
+        product: "Huion Tablet_Q630M",
+        hid_id: 0x58,
+        interfaces: &[
+            (0, VENDOR_RDESC),
+            (1, PEN_RDESC),
+            (2, KEYBOARD_RDESC),
+            (3, MOUSE_RDESC),
+        ],
+    };
+
+    /// v1, reporting on its digitizer interface, with a keyboard
+    /// compatibility interface
+    pub(crate) const HUION_H610_PRO: Self = Self {
+        vid: "256c",
+        pid: "006e",
+        manufacturer: "HUION",
+        product: "PenTablet",
+        hid_id: 0x62,
+        interfaces: &[(0, V1_PEN_RDESC), (1, KEYBOARD_RDESC)],
+    };
+
+    /// Not a tablet
+    pub(crate) const RECEIVER: Self = Self {
+        vid: "046d",
+        pid: "c52b",
+        manufacturer: "Logitech",
+        product: "USB Receiver",
+        hid_id: 0x5e,
+        interfaces: &[(0, KEYBOARD_RDESC), (1, MOUSE_RDESC)],
+    };
+}
+
+impl FakeSysfs {
+    /// An empty tree with the usb and hid buses, and the root hub
+    pub(crate) fn new(name: &str) -> Self {
+        let root =
+            std::env::temp_dir().join(format!("hid-bpf-uclogic-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        for dir in [
+            "bus/usb/devices",
+            "bus/hid/devices",
+            "bus/hid/drivers/hid-generic",
+            "class/hidraw",
+        ] {
+            std::fs::create_dir_all(root.join(dir)).unwrap();
+        }
+
+        let fake = Self {
+            root,
+            next_minor: Cell::new(0),
+        };
+        fake.dir(
+            USB,
+            "usb",
+            &[("idVendor", "1d6b"), ("idProduct", "0002"), ("devnum", "1")],
+        );
+        fake
+    }
+
+    /// Open as [`Sysfs`]
+    pub(crate) fn sysfs(&self) -> Sysfs {
+        Sysfs::open(&self.root).unwrap()
+    }
+
+    /// A device directory in a subsystem, with property files
+    pub(crate) fn dir(&self, path: &str, subsystem: &str, props: &[(&str, &str)]) {
+        let dir = self.root.join(path);
+        std::fs::create_dir_all(&dir).unwrap();
+        std::os::unix::fs::symlink(self.root.join("bus").join(subsystem), dir.join("subsystem"))
+            .unwrap();
+        for (name, value) in props {
+            std::fs::write(dir.join(name), format!("{value}\n")).unwrap();
+        }
+
+        let name = path.rsplit('/').next().unwrap();
+        let link = self.root.join(format!("bus/{subsystem}/devices/{name}"));
+        std::os::unix::fs::symlink(Path::new("../../..").join(path), link).unwrap();
+    }
+
+    /// A USB device on a port of the root hub, with one HID device on each
+    /// interface, named like `0003:VVVV:PPPP.IIII`
+    ///
+    /// Interface 0 is the vendor interface, which is left unbound as
+    /// hid-uclogic does without a BPF driver. The others are bound to
+    /// hid-generic. Each has a hidraw device and an input device with an
+    /// event device.
+    pub(crate) fn usb(&self, port: &str, device: UsbDevice) {
+        let usb = format!("{USB}/{port}");
+        self.dir(
+            &usb,
+            "usb",
+            &[
+                ("idVendor", device.vid),
+                ("idProduct", device.pid),
+                ("manufacturer", device.manufacturer),
+                ("product", device.product),
+                ("devnum", "5"),
+            ],
+        );
+
+        for (i, (num, rdesc)) in device.interfaces.iter().enumerate() {
+            let interface = format!("{usb}/{port}:1.{num}");
+            self.dir(
+                &interface,
+                "usb",
+                &[("bInterfaceNumber", &format!("{num:02x}"))],
+            );
+
+            let name = format!(
+                "0003:{}:{}.{:04X}",
+                device.vid.to_uppercase(),
+                device.pid.to_uppercase(),
+                device.hid_id + i as u16
+            );
+            let hid = format!("{interface}/{name}");
+            self.hid(&hid, 0x03, device, rdesc);
+            if *num != 0 {
+                std::os::unix::fs::symlink(
+                    self.root.join("bus/hid/drivers/hid-generic"),
+                    self.root.join(&hid).join("driver"),
+                )
+                .unwrap();
+            }
+        }
+    }
+
+    /// A Bluetooth HID device, connected through an adapter on a port of the
+    /// root hub, named like `0005:VVVV:PPPP.IIII`
+    ///
+    /// Only the first interface of `device` is used, and
+    /// the device is left unbound. Returns the path of the HID device.
+    pub(crate) fn bluetooth(&self, port: &str, device: UsbDevice) -> PathBuf {
+        let usb = format!("{USB}/{port}");
+        self.dir(
+            &usb,
+            "usb",
+            &[("idVendor", "8087"), ("idProduct", "0026"), ("devnum", "6")],
+        );
+        let interface = format!("{usb}/{port}:1.0");
+        self.dir(&interface, "usb", &[("bInterfaceNumber", "00")]);
+
+        let name = format!(
+            "0005:{}:{}.{:04X}",
+            device.vid.to_uppercase(),
+            device.pid.to_uppercase(),
+            device.hid_id
+        );
+        let hid = format!("{interface}/bluetooth/hci0/hci0:256/{name}");
+        self.hid(&hid, 0x05, device, device.interfaces[0].1);
+        self.root.join(hid)
+    }
+
+    /// A HID device with its uevent, report descriptor, and a hidraw device
+    /// and an input device with an event device
+    fn hid(&self, hid: &str, bus: u16, device: UsbDevice, rdesc: &[u8]) {
+        self.dir(hid, "hid", &[]);
+        let uevent = format!(
+            "HID_ID={bus:04X}:0000{}:0000{}\nHID_NAME={} {}\n",
+            device.vid.to_uppercase(),
+            device.pid.to_uppercase(),
+            device.manufacturer,
+            device.product,
+        );
+        std::fs::write(self.root.join(hid).join("uevent"), uevent).unwrap();
+        std::fs::write(self.root.join(hid).join("report_descriptor"), rdesc).unwrap();
+
+        let minor = self.next_minor.replace(self.next_minor.get() + 1);
+        let nodes = [
+            format!("{hid}/hidraw/hidraw{minor}"),
+            format!("{hid}/input/input{minor}/event{minor}"),
+        ];
+        for node in nodes {
+            std::fs::create_dir_all(self.root.join(node)).unwrap();
+        }
+        std::os::unix::fs::symlink(
+            Path::new("../..").join(format!("{hid}/hidraw/hidraw{minor}")),
+            self.root.join(format!("class/hidraw/hidraw{minor}")),
+        )
+        .unwrap();
+    }
+
+    /// Full path of a USB device added with [`Self::usb`]
+    pub(crate) fn path(&self, port: &str) -> PathBuf {
+        self.root.join(USB).join(port)
+    }
+}
+
+impl Drop for FakeSysfs {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.root);
+    }
+}
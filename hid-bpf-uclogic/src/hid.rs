@@ -0,0 +1,131 @@
+//! HID devices on any transport, identified by `HID_ID` from their uevent
+//!
+//! USB devices are handled through their USB parent, which has the string
+//! descriptors. Bluetooth and virtual (uhid) devices have no such parent, so
+//! the HID device itself stands for the device.
+
+use std::fmt::Display;
+
+use eyre::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::{devices::UsbId, sysfs::SysfsPath};
+
+/// `BUS_*` from `linux/input.h`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Bus {
+    Usb,
+    Bluetooth,
+    Virtual,
+    #[serde(untagged)]
+    Other(u16),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub(crate) struct HidId {
+    pub bus: Bus,
+    /// Vendor and product, which are 16-bit on all buses we support
+    pub id: UsbId,
+}
+
+impl Bus {
+    fn from_raw(bus: u16) -> Self {
+        match bus {
+            0x03 => Self::Usb,
+            0x05 => Self::Bluetooth,
+            0x06 => Self::Virtual,
+            bus => Self::Other(bus),
+        }
+    }
+}
+
+impl Display for Bus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Bus::Usb => write!(f, "USB"),
+            Bus::Bluetooth => write!(f, "Bluetooth"),
+            Bus::Virtual => write!(f, "Virtual"),
+            Bus::Other(bus) => write!(f, "Bus {bus:04X}"),
+        }
+    }
+}
+
+impl HidId {
+    pub(crate) fn usb(id: UsbId) -> Self {
+        Self { bus: Bus::Usb, id }
+    }
+
+    /// Parse `HID_ID`, like `0005:0000256C:00008251`
+    pub(crate) fn parse(s: &str) -> Option<Self> {
+        let mut parts = s.split(':');
+        let bus = u16::from_str_radix(parts.next()?, 16).ok()?;
+        let vid = u32::from_str_radix(parts.next()?, 16).ok()?;
+        let pid = u32::from_str_radix(parts.next()?, 16).ok()?;
+        if parts.next().is_some() {
+            return None;
+        }
+
+        Some(Self {
+            bus: Bus::from_raw(bus),
+            id: UsbId {
+                vid: vid.try_into().ok()?,
+                pid: pid.try_into().ok()?,
+            },
+        })
+    }
+
+    /// From the uevent of a HID device
+    pub(crate) fn read(hid: &SysfsPath) -> Result<Option<Self>> {
+        Ok(uevent_property(hid, "HID_ID")?.and_then(|s| Self::parse(&s)))
+    }
+}
+
+impl TryFrom<String> for HidId {
+    type Error = String;
+
+    /// Like `HID_ID`, zero padding of the ids is optional
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        Self::parse(&value)
+            .ok_or_else(|| format!("Invalid HID id {value:?}, expected bbbb:vvvv:pppp"))
+    }
+}
+
+/// A property in the uevent file of a device
+pub(crate) fn uevent_property(dev: &SysfsPath, name: &str) -> Result<Option<String>> {
+    let Some(uevent) = dev.property("uevent")? else {
+        return Ok(None);
+    };
+    let value = uevent
+        .lines()
+        .filter_map(|l| l.split_once('='))
+        .find(|(k, _)| *k == name)
+        .map(|(_, v)| v.to_owned());
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            HidId::parse("0005:0000256C:00008251"),
+            Some(HidId {
+                bus: Bus::Bluetooth,
+                id: UsbId {
+                    vid: 0x256c,
+                    pid: 0x8251
+                },
+            })
+        );
+        assert_eq!(
+            HidId::parse("0018:000004F3:00003282").map(|h| h.bus),
+            Some(Bus::Other(0x18))
+        );
+        assert_eq!(HidId::parse("0003:0000256C"), None);
+        assert_eq!(HidId::parse("0003:0001256C:00000064"), None);
+    }
+}
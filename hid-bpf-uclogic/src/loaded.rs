@@ -0,0 +1,343 @@
+//! Drivers loaded in the kernel, and what they were loaded with
+//!
+//! Besides what the BPF program uses, `uclogic_config` records the HID id and
+//! device info, so that a loaded driver can be described later without
+//! querying the device again. User settings are in `uclogic_config.settings`.
+
+use std::{
+    ffi::CStr,
+    io,
+    os::fd::{AsFd, AsRawFd},
+};
+
+use eyre::{OptionExt, Result, bail};
+use libbpf_rs::{
+    Link, MapCore, MapFlags, MapHandle, ProgramType,
+    query::{ProgInfoIter, ProgInfoQueryOptions},
+};
+
+use crate::{
+    config::WheelMode,
+    descriptor::{DeviceInfo, Protocol},
+};
+
+/// `sizeof(struct uclogic_config)`
+const CONFIG_SIZE: usize = 656;
+const FIRMWARE_OFFSET: usize = 20;
+const MAGIC_BYTES_OFFSET: usize = 276;
+/// Size of `firmware` and `magic_bytes`
+const INFO_FIELD_SIZE: usize = 256;
+const SETTINGS_OFFSET: usize = 532;
+
+/// Offsets in `struct uclogic_settings`
+const PRESSURE_MAX_OFFSET: usize = 20;
+const PRESSURE_CURVE_OFFSET: usize = 22;
+const FLAGS_OFFSET: usize = 56;
+const BUTTON_MAP_OFFSET: usize = 60;
+
+const SETTING_PRESSURE_CURVE: u8 = 1 << 0;
+const SETTING_BUTTON_MAP: u8 = 1 << 1;
+/// `button_map` entry of a disabled button
+const BUTTON_NONE: u8 = 0xff;
+
+pub(crate) const PRESSURE_CURVE_STEPS: usize = 16;
+pub(crate) const NUM_BUTTON_BITS: usize = 64;
+
+/// Map names are truncated by the kernel, `.rodata.uclogic_rdesc` has the
+/// same one
+const CONFIG_MAP_NAME: &str = ".rodata.uclogic";
+/// Prefix of the program names, also truncated
+const PROG_NAME_PREFIX: &[u8] = b"uclogic_";
+/// `uclogic_fix_event`, which only uses the config map of `.rodata` maps
+const EVENT_PROG_NAME: &[u8] = b"uclogic_fix_eve";
+
+/// Contents of `.rodata.uclogic_config`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Config {
+    pub hid_id: i32,
+    pub collections: u32,
+    pub rdesc_size: u32,
+    pub info: DeviceInfo,
+    pub settings: Settings,
+}
+
+/// Contents of `uclogic_config.settings`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Settings {
+    /// Active area `(x, y, width, height)`, in device units before rotation
+    pub area: [u32; 4],
+    /// Quarter turns clockwise
+    pub rotation: u32,
+    pub pressure_max: u16,
+    /// Output pressure at each step of `pressure_max`, if changed
+    pub pressure_curve: Option<[u16; PRESSURE_CURVE_STEPS + 1]>,
+    /// Input button bit for each output button bit, if changed
+    pub button_map: Option<[Option<u8>; NUM_BUTTON_BITS]>,
+    pub ring: WheelMode,
+    pub dials: WheelMode,
+}
+
+/// A driver in the kernel, pinned or not
+#[derive(Debug)]
+pub(crate) struct LoadedDriver {
+    pub config_map_id: u32,
+    pub prog_ids: Vec<u32>,
+    pub config: Config,
+}
+
+/// Ids of a struct_ops link
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct LinkIds {
+    pub link_id: u32,
+    pub map_id: u32,
+}
+
+impl Config {
+    /// Fill in the initial value of the config map
+    pub(crate) fn write(&self, buf: &mut [u8]) -> Result<()> {
+        if buf.len() != CONFIG_SIZE {
+            bail!(
+                "uclogic_config is {} bytes, expected {CONFIG_SIZE}",
+                buf.len()
+            );
+        }
+
+        let firmware = self.info.firmware.as_bytes();
+        let magic_bytes = &self.info.magic_bytes;
+        // Keep at least one NUL byte after the firmware ID
+        if firmware.len() >= INFO_FIELD_SIZE {
+            bail!("Firmware ID is too long");
+        }
+        if magic_bytes.len() > INFO_FIELD_SIZE {
+            bail!("Device info is too long");
+        }
+
+        let protocol: u32 = match self.info.protocol {
+            Protocol::V1 => 1,
+            Protocol::V2 => 2,
+        };
+        let header = [
+            protocol,
+            self.collections,
+            self.rdesc_size,
+            self.hid_id as u32,
+            magic_bytes.len() as u32,
+        ];
+
+        buf.fill(0);
+        for (dst, val) in buf.chunks_exact_mut(4).zip(header) {
+            dst.copy_from_slice(&val.to_ne_bytes());
+        }
+        buf[FIRMWARE_OFFSET..][..firmware.len()].copy_from_slice(firmware);
+        buf[MAGIC_BYTES_OFFSET..][..magic_bytes.len()].copy_from_slice(magic_bytes);
+        self.settings.write(&mut buf[SETTINGS_OFFSET..]);
+        Ok(())
+    }
+
+    pub(crate) fn read(buf: &[u8]) -> Result<Self> {
+        if buf.len() != CONFIG_SIZE {
+            bail!(
+                "uclogic_config is {} bytes, expected {CONFIG_SIZE}",
+                buf.len()
+            );
+        }
+
+        let word = |i: usize| u32::from_ne_bytes(buf[i * 4..][..4].try_into().unwrap());
+
+        let protocol = match word(0) {
+            1 => Protocol::V1,
+            2 => Protocol::V2,
+            p => bail!("Unknown protocol {p}"),
+        };
+        let magic_bytes_size = word(4) as usize;
+        if magic_bytes_size > INFO_FIELD_SIZE {
+            bail!("Device info size {magic_bytes_size} is too large");
+        }
+        let firmware = CStr::from_bytes_until_nul(&buf[FIRMWARE_OFFSET..][..INFO_FIELD_SIZE])?;
+
+        Ok(Self {
+            hid_id: word(3) as i32,
+            collections: word(1),
+            rdesc_size: word(2),
+            info: DeviceInfo {
+                firmware: firmware.to_str()?.to_owned(),
+                magic_bytes: buf[MAGIC_BYTES_OFFSET..][..magic_bytes_size].to_vec(),
+                protocol,
+            },
+            settings: Settings::read(&buf[SETTINGS_OFFSET..])?,
+        })
+    }
+}
+
+impl Settings {
+    /// Changing nothing, on a tablet of this size
+    pub(crate) fn new(x_max: u32, y_max: u32, pressure_max: u16) -> Self {
+        Self {
+            area: [0, 0, x_max, y_max],
+            rotation: 0,
+            pressure_max,
+            pressure_curve: None,
+            button_map: None,
+            ring: WheelMode::Normal,
+            dials: WheelMode::Normal,
+        }
+    }
+
+    fn write(&self, buf: &mut [u8]) {
+        let header = self.area.into_iter().chain([self.rotation]);
+        for (dst, val) in buf.chunks_exact_mut(4).zip(header) {
+            dst.copy_from_slice(&val.to_ne_bytes());
+        }
+        buf[PRESSURE_MAX_OFFSET..][..2].copy_from_slice(&self.pressure_max.to_ne_bytes());
+
+        let mut flags = 0;
+        if let Some(curve) = &self.pressure_curve {
+            flags |= SETTING_PRESSURE_CURVE;
+            for (dst, val) in buf[PRESSURE_CURVE_OFFSET..].chunks_exact_mut(2).zip(curve) {
+                dst.copy_from_slice(&val.to_ne_bytes());
+            }
+        }
+        if let Some(map) = &self.button_map {
+            flags |= SETTING_BUTTON_MAP;
+            for (dst, src) in buf[BUTTON_MAP_OFFSET..].iter_mut().zip(map) {
+                *dst = src.unwrap_or(BUTTON_NONE);
+            }
+        }
+        buf[FLAGS_OFFSET] = flags;
+        buf[FLAGS_OFFSET + 1] = self.ring.to_raw();
+        buf[FLAGS_OFFSET + 2] = self.dials.to_raw();
+    }
+
+    fn read(buf: &[u8]) -> Result<Self> {
+        let word = |i: usize| u32::from_ne_bytes(buf[i * 4..][..4].try_into().unwrap());
+        let half = |i: usize| u16::from_ne_bytes(buf[i..][..2].try_into().unwrap());
+        let flags = buf[FLAGS_OFFSET];
+
+        let pressure_curve = (flags & SETTING_PRESSURE_CURVE != 0)
+            .then(|| std::array::from_fn(|i| half(PRESSURE_CURVE_OFFSET + i * 2)));
+        let button_map = (flags & SETTING_BUTTON_MAP != 0).then(|| {
+            std::array::from_fn(|i| Some(buf[BUTTON_MAP_OFFSET + i]).filter(|&b| b != BUTTON_NONE))
+        });
+
+        Ok(Self {
+            area: [word(0), word(1), word(2), word(3)],
+            rotation: word(4),
+            pressure_max: half(PRESSURE_MAX_OFFSET),
+            pressure_curve,
+            button_map,
+            ring: WheelMode::from_raw(buf[FLAGS_OFFSET + 1])?,
+            dials: WheelMode::from_raw(buf[FLAGS_OFFSET + 2])?,
+        })
+    }
+}
+
+/// Find the struct_ops map of a link, as a check that it is one of ours
+pub(crate) fn link_ids(link: &Link) -> Result<LinkIds> {
+    let mut info = libbpf_rs::libbpf_sys::bpf_link_info::default();
+    let mut size = size_of_val(&info) as u32;
+    // SAFETY: Pointers are derived from references
+    let res = unsafe {
+        libbpf_rs::libbpf_sys::bpf_link_get_info_by_fd(
+            link.as_fd().as_raw_fd(),
+            &mut info,
+            &mut size,
+        )
+    };
+    if res < 0 {
+        return Err(io::Error::from_raw_os_error(-res).into());
+    }
+
+    if info.type_ != libbpf_rs::libbpf_sys::BPF_LINK_TYPE_STRUCT_OPS {
+        bail!("Link {} is not a struct_ops link", info.id);
+    }
+
+    Ok(LinkIds {
+        link_id: info.id,
+        // SAFETY: struct_ops is the member used by struct_ops links
+        map_id: unsafe { info.__bindgen_anon_1.struct_ops.map_id },
+    })
+}
+
+/// All uclogic drivers in the kernel, identified by their config maps
+///
+/// Without enough privileges, nothing is found.
+pub(crate) fn loaded_drivers() -> Result<Vec<LoadedDriver>> {
+    let progs: Vec<_> =
+        ProgInfoIter::with_query_opts(ProgInfoQueryOptions::default().include_map_ids(true))
+            .filter(|p| matches!(p.ty, ProgramType::StructOps))
+            .filter(|p| p.name.to_bytes().starts_with(PROG_NAME_PREFIX))
+            .collect();
+
+    let mut drivers = Vec::new();
+
+    for prog in progs
+        .iter()
+        .filter(|p| p.name.to_bytes() == EVENT_PROG_NAME)
+    {
+        for &map_id in &prog.map_ids {
+            let map = MapHandle::from_map_id(map_id)?;
+            let info = map.info()?;
+            if info.name()? != CONFIG_MAP_NAME || info.info.value_size as usize != CONFIG_SIZE {
+                continue;
+            }
+
+            let value = map
+                .lookup(&0u32.to_ne_bytes(), MapFlags::ANY)?
+                .ok_or_eyre("Empty config map")?;
+            drivers.push(LoadedDriver {
+                config_map_id: map_id,
+                prog_ids: Vec::new(),
+                config: Config::read(&value)?,
+            });
+        }
+    }
+
+    for prog in &progs {
+        if let Some(driver) = drivers
+            .iter_mut()
+            .find(|d| prog.map_ids.contains(&d.config_map_id))
+        {
+            driver.prog_ids.push(prog.id);
+        }
+    }
+
+    drivers.sort_by_key(|d| d.config.hid_id);
+    Ok(drivers)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_config() {
+        let info = include_str!("../fixtures/device-info/gaomon-m7.txt");
+        let mut config = Config {
+            hid_id: 0x4c,
+            collections: 0b11,
+            rdesc_size: 292,
+            info: DeviceInfo::from_str(info).unwrap(),
+            settings: Settings::new(51689, 34308, 8191),
+        };
+
+        let mut buf = vec![0xaa; CONFIG_SIZE];
+        config.write(&mut buf).unwrap();
+        assert_eq!(Config::read(&buf).unwrap(), config);
+
+        config.settings = Settings {
+            area: [100, 200, 25000, 17000],
+            rotation: 3,
+            pressure_max: 8191,
+            pressure_curve: Some(std::array::from_fn(|i| i as u16 * 500)),
+            button_map: Some(std::array::from_fn(|i| (i != 1).then_some(i as u8 ^ 1))),
+            ring: WheelMode::Off,
+            dials: WheelMode::Reversed,
+        };
+        config.write(&mut buf).unwrap();
+        assert_eq!(Config::read(&buf).unwrap(), config);
+
+        let mut long = config.clone();
+        long.info.firmware = "x".repeat(INFO_FIELD_SIZE);
+        assert!(long.write(&mut buf).is_err());
+    }
+}
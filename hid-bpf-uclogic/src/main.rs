@@ -1,39 +1,78 @@
+mod config;
+mod daemon;
+mod decode;
 mod descriptor;
+mod devices;
+mod doctor;
+#[cfg(test)]
+mod fixture;
+mod hid;
+mod loaded;
+mod properties;
+mod rdesc;
+mod records;
+mod reports;
+mod selector;
 mod sysfs;
+mod udev;
+mod uevent;
+mod usbfs;
+mod verify;
 
-use libbpf_rs::{Link, MapCore, ObjectBuilder};
+use libbpf_rs::{Link, MapCore, ObjectBuilder, OpenObject};
 use std::{
     collections::HashMap,
     ffi::{CStr, OsStr, OsString},
     io,
     path::{Path, PathBuf},
 };
+use descriptor::Protocol;
+use devices::{DeviceDb, Quirk, UsbId};
+use hid::Bus;
+use records::{Format, InterfaceKind};
 use sysfs::{Sysfs, SysfsPath};
 
 use eyre::{Context, OptionExt, Result, bail, eyre};
 
-static SUPPORTED_VID_PID: &[(u32, u32)] = &[
-    // Gaomon M6
-    // Gaomon M7
-    // Huion HC16
-    (0x256c, 0x0064),
-    // Huion Inspiroy Dial 2
-    (0x256c, 0x0060),
-];
-
-static SUPPORTED_FIRMWARE: &[&str] = &[
-    "GM001_T207_210524", // Gaomon M7
-    "HUION_T18C_211220", // Huion HC16
-    "HUION_T216_220819", // Huion Inspiroy Dial 2
-];
-
 struct Args {
-    device: OsString,
+    /// All supported devices if not given, with `--all`
+    device: Option<OsString>,
     with_huion_switcher: Option<OsString>,
     device_info: Option<OsString>,
+    from_env: bool,
+    from_udev_db: bool,
     force: bool,
     quiet: bool,
     wait: bool,
+    dump_descriptor: bool,
+    dry_run: bool,
+    format: Format,
+    /// Instead of the system and user config files
+    config: Option<PathBuf>,
+}
+
+struct DaemonArgs {
+    with_huion_switcher: Option<OsString>,
+    quiet: bool,
+}
+
+struct InstallUdevArgs {
+    prefix: PathBuf,
+    bin: Option<PathBuf>,
+    huion_switcher: PathBuf,
+    dry_run: bool,
+}
+
+struct StatusArgs {
+    remove_stale: bool,
+    format: Format,
+}
+
+struct UnloadArgs {
+    device: Option<OsString>,
+    all: bool,
+    reset: bool,
+    quiet: bool,
 }
 
 fn main() -> Result<()> {
@@ -43,17 +82,41 @@ fn main() -> Result<()> {
         s.lines().for_each(|l| eprintln!("libbpf: {level:?} {l}"))
     })));
 
+    // Listing only takes --format
+    let load_args = [
+        "device",
+        "all",
+        "quiet",
+        "wait",
+        "device-info",
+        "with-huion-switcher",
+        "from-env",
+        "from-udev-db",
+        "force",
+        "dump-descriptor",
+        "dry-run",
+        "config",
+    ];
+
     let args = clap::Command::new("hid-bpf-uclogic")
         .version(env!("CARGO_PKG_VERSION"))
         .arg_required_else_help(true)
+        .subcommand_negates_reqs(true)
         .arg(
             Arg::new("device")
-                .required(true)
+                .required_unless_present("all")
                 .long("device")
-                .value_name("syspath")
-                .help("/sys path of device")
+                .value_name("device")
+                .help("/sys path of USB device, vvvv:pppp, hid:IIII, /dev/hidrawN, or auto")
                 .value_parser(clap::value_parser!(OsString)),
         )
+        .arg(
+            Arg::new("all")
+                .long("all")
+                .action(ArgAction::SetTrue)
+                .conflicts_with_all(["device", "device-info", "from-env"])
+                .help("Load for all supported devices"),
+        )
         .arg(
             Arg::new("quiet")
                 .long("quiet")
@@ -70,7 +133,7 @@ fn main() -> Result<()> {
             Arg::new("device-info")
                 .long("device-info")
                 .value_name("file")
-                .help("File to read instead of calling huion-switcher")
+                .help("File to read instead of querying the device")
                 .value_parser(clap::value_parser!(OsString)),
         )
         .arg(
@@ -78,7 +141,28 @@ fn main() -> Result<()> {
                 .long("with-huion-switcher")
                 .value_name("path")
                 .value_parser(clap::value_parser!(OsString))
-                .help("Path to huion-switcher"),
+                .help("Call huion-switcher instead of querying the device directly"),
+        )
+        .arg(
+            Arg::new("from-env")
+                .long("from-env")
+                .action(ArgAction::SetTrue)
+                .conflicts_with_all(["device-info", "with-huion-switcher"])
+                .help("Take device info from the environment, when run from a udev rule"),
+        )
+        .arg(
+            Arg::new("from-udev-db")
+                .long("from-udev-db")
+                .action(ArgAction::SetTrue)
+                .conflicts_with_all(["device-info", "with-huion-switcher", "from-env"])
+                .help("Take device info from the udev database"),
+        )
+        .arg(
+            Arg::new("config")
+                .long("config")
+                .value_name("file")
+                .value_parser(clap::value_parser!(PathBuf))
+                .help("Settings file to use instead of the system and user ones"),
         )
         .arg(
             Arg::new("force")
@@ -86,182 +170,1170 @@ fn main() -> Result<()> {
                 .action(ArgAction::SetTrue)
                 .help("Bypass some checks while loading"),
         )
+        .arg(
+            Arg::new("dump-descriptor")
+                .long("dump-descriptor")
+                .action(ArgAction::SetTrue)
+                .help("Show the generated and original report descriptors instead of loading"),
+        )
+        .arg(
+            Arg::new("dry-run")
+                .long("dry-run")
+                .action(ArgAction::SetTrue)
+                .conflicts_with("dump-descriptor")
+                .help("Show what loading would do, without changing anything"),
+        )
+        .arg(
+            Arg::new("format")
+                .long("format")
+                .value_name("format")
+                .value_parser(["text", "json"])
+                .default_value("text")
+                .conflicts_with("dump-descriptor")
+                .help("Output format of listing and loading"),
+        )
         .arg(
             Arg::new("list-devices")
-                .exclusive(true)
+                .conflicts_with_all(load_args)
                 .long("list-devices")
                 .action(ArgAction::SetTrue)
                 .help("List supported USB HID devices"),
         )
         .arg(
             Arg::new("list-devices-all")
-                .exclusive(true)
+                .conflicts_with_all(load_args)
+                .conflicts_with("list-devices")
                 .long("list-devices-all")
                 .action(ArgAction::SetTrue)
-                .help("List all USB HID devices"),
+                .help("List all HID devices"),
+        )
+        .subcommand(
+            clap::Command::new("unload")
+                .about("Unload the driver and restore default device functionality")
+                .arg(
+                    Arg::new("device")
+                        .required_unless_present("all")
+                        .value_name("device")
+                        .help("/sys path of USB device, or HID id of vendor interface")
+                        .value_parser(clap::value_parser!(OsString)),
+                )
+                .arg(
+                    Arg::new("all")
+                        .long("all")
+                        .conflicts_with("device")
+                        .action(ArgAction::SetTrue)
+                        .help("Unload from all devices"),
+                )
+                .arg(
+                    Arg::new("reset")
+                        .long("reset")
+                        .action(ArgAction::SetTrue)
+                        .help("Reset the USB device afterwards, same as replugging"),
+                )
+                .arg(
+                    Arg::new("quiet")
+                        .long("quiet")
+                        .action(ArgAction::SetTrue)
+                        .help("Omit informational messages"),
+                ),
+        )
+        .subcommand(
+            clap::Command::new("status")
+                .about("Show loaded drivers and the devices they were loaded for")
+                .arg(
+                    Arg::new("remove-stale")
+                        .long("remove-stale")
+                        .action(ArgAction::SetTrue)
+                        .help("Remove pinned drivers of devices that no longer exist"),
+                )
+                .arg(
+                    Arg::new("format")
+                        .long("format")
+                        .value_name("format")
+                        .value_parser(["text", "json"])
+                        .default_value("text")
+                        .help("Output format"),
+                ),
+        )
+        .subcommand(
+            clap::Command::new("daemon")
+                .about("Load the driver whenever a supported device is plugged in")
+                .arg(
+                    Arg::new("with-huion-switcher")
+                        .long("with-huion-switcher")
+                        .value_name("path")
+                        .value_parser(clap::value_parser!(OsString))
+                        .help("Call huion-switcher instead of querying the device directly"),
+                )
+                .arg(
+                    Arg::new("quiet")
+                        .long("quiet")
+                        .action(ArgAction::SetTrue)
+                        .help("Omit informational messages"),
+                ),
+        )
+        .subcommand(
+            clap::Command::new("doctor")
+                .about("Check what loading the driver needs, with hints for fixing it")
+                .arg(
+                    Arg::new("huion-switcher")
+                        .long("huion-switcher")
+                        .value_name("path")
+                        .default_value("huion-switcher")
+                        .value_parser(clap::value_parser!(PathBuf))
+                        .help("huion-switcher to look for, in PATH and the udev directories"),
+                ),
+        )
+        .subcommand(
+            clap::Command::new("install-udev")
+                .about("Install udev rules and systemd units that load the driver for supported devices")
+                .arg(
+                    Arg::new("prefix")
+                        .long("prefix")
+                        .value_name("dir")
+                        .default_value("/etc")
+                        .value_parser(clap::value_parser!(PathBuf))
+                        .help("Directory containing udev/ and systemd/, such as /usr/lib"),
+                )
+                .arg(
+                    Arg::new("bin")
+                        .long("bin")
+                        .value_name("path")
+                        .value_parser(clap::value_parser!(PathBuf))
+                        .help("hid-bpf-uclogic to run [default: this executable]"),
+                )
+                .arg(
+                    Arg::new("huion-switcher")
+                        .long("huion-switcher")
+                        .value_name("path")
+                        .default_value("huion-switcher")
+                        .value_parser(clap::value_parser!(PathBuf))
+                        .help("huion-switcher to import properties from, relative to the udev directory"),
+                )
+                .arg(
+                    Arg::new("dry-run")
+                        .long("dry-run")
+                        .action(ArgAction::SetTrue)
+                        .help("Show the files instead of writing them"),
+                ),
         )
         .get_matches();
 
+    if let Some(("install-udev", args)) = args.subcommand() {
+        return install_udev(&InstallUdevArgs {
+            prefix: args.get_one::<PathBuf>("prefix").unwrap().clone(),
+            bin: args.get_one("bin").cloned(),
+            huion_switcher: args.get_one::<PathBuf>("huion-switcher").unwrap().clone(),
+            dry_run: args.get_flag("dry-run"),
+        });
+    }
+
     let sysfs = Sysfs::get()?;
     let sysfs = sysfs.root()?;
 
+    if let Some(("doctor", args)) = args.subcommand() {
+        let huion_switcher = args.get_one::<PathBuf>("huion-switcher").unwrap();
+        let db = DeviceDb::load()?;
+        return doctor::report(&doctor::checks(&sysfs, &db, huion_switcher));
+    }
+
+    if let Some(("unload", args)) = args.subcommand() {
+        return unload(
+            &sysfs,
+            &UnloadArgs {
+                device: args.get_one("device").cloned(),
+                all: args.get_flag("all"),
+                reset: args.get_flag("reset"),
+                quiet: args.get_flag("quiet"),
+            },
+        );
+    }
+
+    let db = DeviceDb::load()?;
+
+    if let Some(("daemon", args)) = args.subcommand() {
+        return daemon(
+            &sysfs,
+            &db,
+            &DaemonArgs {
+                with_huion_switcher: args.get_one("with-huion-switcher").cloned(),
+                quiet: args.get_flag("quiet"),
+            },
+        );
+    }
+
+    if let Some(("status", args)) = args.subcommand() {
+        return status(
+            &sysfs,
+            &db,
+            &StatusArgs {
+                remove_stale: args.get_flag("remove-stale"),
+                format: Format::parse(args.get_one::<String>("format").unwrap())?,
+            },
+        );
+    }
+
     if args.get_flag("list-devices") || args.get_flag("list-devices-all") {
         let show_all = args.get_flag("list-devices-all");
 
-        list_devices(&sysfs, show_all)?;
+        let format = Format::parse(args.get_one::<String>("format").unwrap())?;
+
+        list_devices(&sysfs, &db, show_all, format, &mut io::stdout().lock())?;
     } else {
         load(
             &sysfs,
+            &db,
             &Args {
-                device: args.get_one::<OsString>("device").unwrap().clone(),
+                device: args.get_one("device").cloned(),
                 with_huion_switcher: args.get_one("with-huion-switcher").cloned(),
                 device_info: args.get_one("device-info").cloned(),
+                from_env: args.get_flag("from-env"),
+                from_udev_db: args.get_flag("from-udev-db"),
                 force: args.get_flag("force"),
                 wait: args.get_flag("wait"),
                 quiet: args.get_flag("quiet"),
+                dump_descriptor: args.get_flag("dump-descriptor"),
+                dry_run: args.get_flag("dry-run"),
+                format: Format::parse(args.get_one::<String>("format").unwrap())?,
+                config: args.get_one("config").cloned(),
             },
         )?;
     }
     Ok(())
 }
 
-fn load(sysfs: &SysfsPath, args: &Args) -> Result<()> {
-    use std::os::unix::ffi::OsStrExt;
+/// Checks and steps of `load --dry-run`
+///
+/// When not a dry run, failed checks are returned as errors right away.
+struct Plan {
+    dry_run: bool,
+    checks: Vec<(String, Option<eyre::Report>)>,
+    steps: Vec<String>,
+}
+
+impl Plan {
+    fn new(dry_run: bool) -> Self {
+        Self {
+            dry_run,
+            checks: Vec::new(),
+            steps: Vec::new(),
+        }
+    }
+
+    fn check(&mut self, what: impl Into<String>, result: Result<()>) -> Result<()> {
+        if !self.dry_run {
+            return result;
+        }
+        self.checks.push((what.into(), result.err()));
+        Ok(())
+    }
 
-    if !args.wait {
-        find_bpffs()?;
+    fn step(&mut self, step: impl Into<String>) {
+        self.steps.push(step.into());
     }
 
-    let device = sysfs
-        .sub(&PathBuf::from(&args.device))?
-        .ok_or_eyre("Device not found")?;
+    /// Print the plan, or add it to the record in `records` for JSON
+    fn finish(
+        self,
+        rdesc: &[u8],
+        format: Format,
+        mut record: records::Load,
+        records: &mut Vec<records::Load>,
+    ) -> Result<()> {
+        match format {
+            Format::Text => {
+                println!("Checks:");
+                for (what, err) in &self.checks {
+                    match err {
+                        None => println!("  ok    {what}"),
+                        Some(e) => println!("  FAIL  {what}: {e:#}"),
+                    }
+                }
+                println!("Steps:");
+                for step in &self.steps {
+                    println!("  {step}");
+                }
+                print!("Descriptor:\n\n{}", decode::dump(rdesc));
+            }
+            Format::Json => {
+                let checks = self.checks.iter().map(|(what, err)| records::Check {
+                    check: what.clone(),
+                    error: err.as_ref().map(|e| format!("{e:#}")),
+                });
+                record.plan = Some(records::Plan {
+                    checks: checks.collect(),
+                    steps: self.steps.clone(),
+                });
+                records.push(record);
+            }
+        }
+
+        let failed = self.checks.iter().filter(|(_, e)| e.is_some()).count();
+        if failed > 0 {
+            bail!("{failed} of {} checks failed", self.checks.len());
+        }
+        Ok(())
+    }
+}
 
-    let usb_hid = find_usb_hid(sysfs)?;
+/// A driver loaded with `--wait`, unloaded on a signal
+struct Held<'a> {
+    hid_id: i32,
+    link: Link,
+    unbound: Vec<(&'a OsStr, PathBuf)>,
+}
 
-    let Some(hids) = usb_hid.get(&device) else {
-        bail!("Device does not seem to be a relevant USB device.");
+fn load(sysfs: &SysfsPath, db: &DeviceDb, args: &Args) -> Result<()> {
+    if args.dry_run && args.device_info.is_none() && !args.from_env && !args.from_udev_db {
+        bail!(
+            "Querying the device changes its mode, use --dry-run with --device-info, --from-env or --from-udev-db"
+        );
+    }
+
+    // Signals while loading are handled once loaded, so nothing is left
+    // half done
+    let signals = if args.wait && !args.dry_run {
+        Some(block_signals()?)
+    } else {
+        None
     };
 
-    if !args.force {
-        if !usb_supported(&device)? {
-            bail!("Device is not supported (Use --force to load anyway)");
+    let user_config = config::UserConfig::load(args.config.as_deref())?;
+    let usb_hid = find_hid_devices(sysfs)?;
+
+    if let Some(device) = &args.device {
+        let selector = selector::Selector::parse(device)?;
+        let device = selector::resolve(sysfs, db, &selector)?;
+        let Some(hids) = usb_hid.get(&device) else {
+            bail!("Device does not seem to be a relevant USB device.");
+        };
+
+        // Written even if loading fails, for the plan of a dry run
+        let mut records = Vec::new();
+        let held = load_device(sysfs, db, &user_config, args, &device, hids, &mut records);
+        if let Some(record) = records.first() {
+            records::write(&mut io::stdout().lock(), record)?;
+        }
+        // Nothing is held after --dump-descriptor
+        if let (Some(signals), Some(held)) = (&signals, held?) {
+            hold(sysfs, signals, vec![held], args.quiet)?;
         }
+        return Ok(());
     }
 
-    if !args.quiet {
-        print_usb_device(&device)?;
+    let devices = supported_devices(&usb_hid, db)?;
+    if devices.is_empty() {
+        bail!("No supported device found");
+    }
+
+    // Each device is loaded on its own, so that one failing does not keep
+    // the others from loading
+    let mut held = Vec::new();
+    let mut records = Vec::new();
+    let mut failed = 0;
+    for (path, usb) in &devices {
+        let hids = &usb_hid[*usb];
+        match load_device(sysfs, db, &user_config, args, usb, hids, &mut records) {
+            Ok(h) => held.extend(h),
+            Err(e) => {
+                eprintln!("Failed to load driver for {}: {e:#}", path.display());
+                failed += 1;
+            }
+        }
+    }
+    if !args.quiet || failed > 0 {
+        eprintln!(
+            "Loaded {} of {} devices",
+            devices.len() - failed,
+            devices.len()
+        );
+    }
+    if args.format == Format::Json {
+        records::write(&mut io::stdout().lock(), &records)?;
     }
 
-    let device_info = if let Some(device_info) = &args.device_info {
-        std::fs::read_to_string(device_info).wrap_err_with(|| {
+    if let Some(signals) = &signals
+        && !held.is_empty()
+    {
+        hold(sysfs, signals, held, args.quiet)?;
+    }
+    if failed > 0 {
+        bail!("{failed} of {} devices failed to load", devices.len());
+    }
+    Ok(())
+}
+
+/// Supported devices for `--all`, sorted by syspath
+fn supported_devices<'a, 'b, T>(
+    usb_hid: &'b HashMap<SysfsPath<'a>, T>,
+    db: &DeviceDb,
+) -> Result<Vec<(PathBuf, &'b SysfsPath<'a>)>> {
+    let mut devices = Vec::new();
+    for usb in usb_hid.keys() {
+        if device_supported(usb, db)? {
+            devices.push((usb.recover_path()?, usb));
+        }
+    }
+    devices.sort_by(|a, b| a.0.cmp(&b.0));
+    Ok(devices)
+}
+
+/// Wait for a signal, then unload drivers held by `--wait`
+fn hold(sysfs: &SysfsPath, signals: &libc::sigset_t, held: Vec<Held>, quiet: bool) -> Result<()> {
+    let hid_bus = sysfs
+        .get_subsystem("hid")?
+        .ok_or_eyre("No hid subsystem found in sysfs")?;
+
+    eprintln!("Driver loaded, Ctrl-C to terminate and unload");
+    let signal = wait_signal(signals)?;
+    if !quiet {
+        eprintln!("Received {}, unloading", signal_name(signal));
+    }
+
+    let mut result = Ok(());
+    for Held {
+        hid_id,
+        link,
+        unbound,
+    } in held
+    {
+        drop(link);
+        if !quiet {
+            eprintln!("Detached driver from HID {hid_id:04X}");
+        }
+        if let Err(e) = restore(&hid_bus, &unbound, quiet) {
+            result = Err(e);
+        }
+    }
+    result
+}
+
+/// Load the driver for one device, returning it if held by `--wait`
+fn load_device<'a>(
+    sysfs: &SysfsPath,
+    db: &DeviceDb,
+    user_config: &config::UserConfig,
+    args: &Args,
+    device: &SysfsPath,
+    hids: &'a [(u8, OsString, SysfsPath)],
+    records: &mut Vec<records::Load>,
+) -> Result<Option<Held<'a>>> {
+    use std::os::unix::ffi::OsStrExt;
+
+    let mut plan = Plan::new(args.dry_run);
+
+    if !args.wait && !args.dump_descriptor {
+        plan.check("bpffs is mounted", find_bpffs().map(drop))?;
+    }
+
+    if !args.force {
+        let supported = device_supported(device, db)?;
+        plan.check(
+            "USB id is in the device database",
+            supported
+                .then_some(())
+                .ok_or_eyre("Device is not supported (Use --force to load anyway)"),
+        )?;
+    }
+
+    let usb_record = device_record(device, hids, db)?;
+    if !args.quiet && args.format == Format::Text {
+        print_device(&usb_record, &mut io::stdout().lock())?;
+    }
+
+    let info = if let Some(device_info) = &args.device_info {
+        let device_info = std::fs::read_to_string(device_info).wrap_err_with(|| {
             format!("Reading device info from {}", device_info.to_string_lossy())
-        })?
+        })?;
+        descriptor::DeviceInfo::from_str(&device_info).wrap_err("Failed to parse device info")?
+    } else if let Some(huion_switcher) = &args.with_huion_switcher {
+        require_usb(device)?;
+        let device_info = call_huion_switcher(&device.recover_path()?, huion_switcher, args.quiet)
+            .wrap_err("Error running huion-switcher")?;
+        descriptor::DeviceInfo::from_str(&device_info).wrap_err("Failed to parse device info")?
+    } else if args.from_env {
+        properties::from_env().wrap_err("Failed to read device info from the environment")?
+    } else if args.from_udev_db {
+        properties::from_udev_db(device)
+            .wrap_err("Failed to read device info from the udev database")?
     } else {
-        let huion_switcher = args
-            .with_huion_switcher
-            .clone()
-            .unwrap_or("huion-switcher".into());
-
-        call_huion_switcher(&PathBuf::from(&args.device), huion_switcher, args.quiet)
-            .wrap_err("Error running huion-switcher")?
+        read_device_info(device, args.quiet).wrap_err("Error querying device")?
     };
 
-    let info =
-        descriptor::DeviceInfo::from_str(&device_info).wrap_err("Failed to parse device info")?;
-
     if !args.quiet {
-        eprintln!("Found device id {:?}", info.firmware);
+        eprintln!(
+            "Found device id {:?} (protocol {})",
+            info.firmware, info.protocol
+        );
     }
 
-    if !args.force && !SUPPORTED_FIRMWARE.contains(&info.firmware.as_str()) {
-        bail!(format!(
-            "Unsupported device {:?} (Use --force to load anyway)",
-            info.firmware
-        ));
+    let entry = db.by_firmware(device_id(device)?, &info.firmware);
+
+    if !args.force {
+        plan.check(
+            "Device id is in the device database",
+            entry.map(drop).ok_or_else(|| {
+                eyre!(
+                    "Unsupported device {:?} (Use --force to load anyway)",
+                    info.firmware
+                )
+            }),
+        )?;
     }
 
-    let parsed = info.parse()?;
+    match entry {
+        Some(entry) if !args.quiet => {
+            eprintln!("Matched {} ({})", entry.name, entry.features);
+        }
+        _ => (),
+    }
+
+    let keep_compat = entry.is_some_and(|e| e.has_quirk(Quirk::KeepCompatInterfaces));
+
+    let mut parsed = info.parse()?;
+    if let Some(entry) = entry {
+        parsed.apply_features(&entry.features);
+    }
+    let usb_id = device_id(device)?.map(|h| h.id);
+    let device_config = user_config.for_device(usb_id, &info.firmware);
+    let settings = device_config
+        .apply(&mut parsed)
+        .wrap_err("Invalid settings in config file")?;
     if !args.quiet {
+        if !device_config.is_empty() {
+            eprintln!("Settings: {device_config}");
+        }
         eprintln!("{}", parsed);
     }
     let new_rdesc = parsed.descriptor()?;
+    plan.check(
+        "Descriptor matches the reports of the BPF program",
+        reports::check(&new_rdesc)
+            .wrap_err("Generated descriptor does not match the reports of the BPF program"),
+    )?;
+    let size_ok = (1..=HID_MAX_DESCRIPTOR_SIZE).contains(&new_rdesc.len());
+    plan.check(
+        "Descriptor size is accepted by the kernel",
+        size_ok.then_some(()).ok_or_else(|| {
+            eyre!(
+                "Generated descriptor is {} bytes, must be between 1 and {HID_MAX_DESCRIPTOR_SIZE}",
+                new_rdesc.len()
+            )
+        }),
+    )?;
+
+    if args.dump_descriptor {
+        let Some((_, _, hid)) = hids.iter().find(|(num, ..)| *num == 0) else {
+            bail!("No vendor interface found");
+        };
+        let orig_rdesc = hid.read("report_descriptor")?;
+        print!("Generated report descriptor:\n\n{}", decode::dump(&new_rdesc));
+        print!(
+            "\nReport descriptor of {}:\n\n{}",
+            hid.recover_path()?.display(),
+            decode::dump(&orig_rdesc)
+        );
+        return Ok(None);
+    }
+
+    let Some((_, vendor_name, hid_dev)) = hids.iter().find(|(num, ..)| *num == 0) else {
+        bail!("No vendor interface found");
+    };
+    let hid_id = parse_hid_id(vendor_name)
+        .ok_or_else(|| eyre!("Unexpected HID device name {:?}", vendor_name))?;
+
+    let bpffs_name = pin_path(hid_id);
+
+    let record = records::Load {
+        device: usb_record,
+        hid_id: records::hid_id(hid_id),
+        firmware: info.firmware.clone(),
+        protocol: info.protocol,
+        matched: entry.map(records::Match::new),
+        params: parsed.clone(),
+        settings: device_config,
+        descriptor_size: new_rdesc.len(),
+        pin: (!args.wait).then(|| bpffs_name.clone()),
+        plan: None,
+    };
+
+    let exists = PathBuf::from(&bpffs_name).exists();
+    if args.force {
+        if exists {
+            eprintln!("Driver already exists at {bpffs_name}");
+        }
+    } else {
+        plan.check(
+            "No driver is pinned for the device",
+            (!exists).then_some(()).ok_or_else(|| {
+                eyre!("Driver already exists, to remove: hid-bpf-uclogic unload {hid_id:04X}")
+            }),
+        )?;
+    }
+
+    let orig_rdesc = hid_dev.read("report_descriptor")?;
+    if !args.force {
+        // v2 devices have a vendor-defined (0xff00) interface, v1 devices
+        // report on their digitizer interface
+        let expected: &[u8] = match parsed.protocol {
+            Protocol::V1 => &[0x05, 0x0d],
+            Protocol::V2 => &[0x06, 0x00, 0xff],
+        };
+
+        plan.check(
+            "Vendor interface has its original descriptor",
+            orig_rdesc.starts_with(expected).then_some(()).ok_or_eyre(
+                "Found HID device with unexpected descriptor. Is a driver already loaded?",
+            ),
+        )?;
+    }
+
+    let config = loaded::Config {
+        hid_id,
+        collections: parsed.collections(),
+        rdesc_size: u32::try_from(new_rdesc.len()).unwrap(),
+        info,
+        settings,
+    };
 
-    let mut hid_dev: Option<(i32, &SysfsPath)> = None;
+    let hid_bus = sysfs
+        .get_subsystem("hid")?
+        .ok_or_eyre("No hid subsystem found in sysfs")?;
 
-    for (num, hid_name, hid) in hids.into_iter().rev() {
-        if *num == 0 {
-            let id = parse_hid_id(&hid_name)
-                .ok_or_else(|| eyre!("Unexpected HID device name {:?}", hid_name))?;
-            hid_dev = Some((id, hid));
+    // Everything unbound from here on is rebound if loading fails
+    let mut unbound: Vec<(&OsStr, PathBuf)> = Vec::new();
+
+    for (num, hid_name, hid) in hids.iter().rev() {
+        if *num == 0 || keep_compat {
             continue;
         }
 
+        let path = hid.recover_path()?;
+        if args.dry_run {
+            if hid.sub("driver")?.is_some() {
+                plan.step(format!("Unbind compatibility device {}", path.display()));
+            }
+            continue;
+        }
         if !args.quiet {
-            eprintln!(
-                "Unbinding compatibility device {}",
-                hid.recover_path()?.display()
-            );
+            eprintln!("Unbinding compatibility device {}", path.display());
         }
 
         let res = hid.write("driver/unbind", hid_name.as_bytes());
         match res {
-            Ok(()) => (),
+            Ok(()) => unbound.push((hid_name, path)),
             Err(e) if e.kind() == io::ErrorKind::NotFound => (),
-            Err(e) => Err(e)?,
+            Err(e) => return Err(rollback(&hid_bus, &unbound, args.quiet, e.into())),
         }
     }
 
-    let Some((hid_id, hid_dev)) = hid_dev else {
-        bail!("No vendor interface found");
+    if args.dry_run {
+        plan.check(
+            "BPF object accepts the config and descriptor",
+            open_bpf(&config, &new_rdesc)
+                .map(drop)
+                .wrap_err("Cannot prepare BPF"),
+        )?;
+        plan.step(format!(
+            "Load BPF for HID {hid_id:04X}, with the {} byte descriptor below",
+            new_rdesc.len()
+        ));
+        plan.step(format!(
+            "Check that HID {hid_id:04X} has the new descriptor and input devices"
+        ));
+        if args.wait {
+            plan.step("Wait for a signal, then unload");
+        } else {
+            plan.step(format!("Pin at {bpffs_name}"));
+        }
+        plan.finish(&new_rdesc, args.format, record, records)?;
+        return Ok(None);
+    }
+
+    let link = fixup_device(&config, &new_rdesc).map_err(|e| {
+        match e.downcast_ref::<libbpf_rs::Error>() {
+            Some(ioe) if ioe.kind() == libbpf_rs::ErrorKind::PermissionDenied => {
+                e.wrap_err("Cannot load BPF (Try running as root?)")
+            }
+            _ => e.wrap_err("Cannot load BPF (Check with: hid-bpf-uclogic doctor)"),
+        }
+    });
+    let link = match link {
+        Ok(link) => link,
+        Err(e) => return Err(rollback(&hid_bus, &unbound, args.quiet, e)),
     };
 
-    let bpffs_name = format!("/sys/fs/bpf/hid-bpf-uclogic-{hid_id:04X}");
+    let fixed = verify::wait_fixed(
+        &hid_bus,
+        vendor_name,
+        &orig_rdesc,
+        &new_rdesc,
+        verify::TIMEOUT,
+    );
+    match fixed {
+        Ok(inputs) if !args.quiet => {
+            let inputs: Vec<_> = inputs.iter().map(|n| n.to_string_lossy()).collect();
+            eprintln!("Fixup in effect, input devices {}", inputs.join(", "));
+        }
+        Ok(_) => (),
+        Err(e) => {
+            // Detaching makes the kernel probe the device again as it was
+            drop(link);
+            let e = e.wrap_err("BPF attached, but the fixup did not take effect");
+            return Err(rollback(&hid_bus, &unbound, args.quiet, e));
+        }
+    }
 
-    if PathBuf::from(&bpffs_name).exists() {
-        if args.force {
-            eprintln!("Driver already exists at {bpffs_name}");
+    if args.wait {
+        if args.format == Format::Json {
+            records.push(record);
+        }
+        return Ok(Some(Held {
+            hid_id,
+            link,
+            unbound,
+        }));
+    }
+
+    if let Err(e) = { link }.pin(&bpffs_name) {
+        let e = eyre::Report::from(e).wrap_err("Failed to pin BPF link");
+        return Err(rollback(&hid_bus, &unbound, args.quiet, e));
+    }
+    if args.format == Format::Json {
+        records.push(record);
+    }
+    if !args.quiet {
+        eprintln!("Driver loaded, to unload: hid-bpf-uclogic unload {hid_id:04X}");
+    }
+
+    Ok(None)
+}
+
+/// Rebind compatibility interfaces unbound by `load`
+fn restore(hid_bus: &SysfsPath, unbound: &[(&OsStr, PathBuf)], quiet: bool) -> Result<()> {
+    let mut result = Ok(());
+    // Keep going, so that one failure does not leave the others unbound
+    for (hid_name, path) in unbound {
+        if let Err(e) = rebind(hid_bus, hid_name, path, quiet) {
+            eprintln!("{e:#}");
+            result = Err(eyre!("Failed to restore compatibility devices"));
+        }
+    }
+    result
+}
+
+/// Undo unbinding after a failed load, returning the original error
+fn rollback(
+    hid_bus: &SysfsPath,
+    unbound: &[(&OsStr, PathBuf)],
+    quiet: bool,
+    err: eyre::Report,
+) -> eyre::Report {
+    if let Err(e) = restore(hid_bus, unbound, quiet) {
+        eprintln!("{e:#}");
+    }
+    err
+}
+
+fn rebind(hid_bus: &SysfsPath, hid_name: &OsStr, path: &Path, quiet: bool) -> Result<()> {
+    use std::os::unix::ffi::OsStrExt;
+
+    if !quiet {
+        eprintln!("Rebinding compatibility device {}", path.display());
+    }
+    hid_bus
+        .write("drivers_probe", hid_name.as_bytes())
+        .wrap_err_with(|| format!("Failed to rebind {}", path.display()))
+}
+
+/// Block termination signals, so that `wait_signal` can handle them
+fn block_signals() -> io::Result<libc::sigset_t> {
+    use std::mem::MaybeUninit;
+
+    // SAFETY: set is initialized by sigemptyset before use
+    unsafe {
+        let mut set = MaybeUninit::uninit();
+        libc::sigemptyset(set.as_mut_ptr());
+        let mut set = set.assume_init();
+        for signal in [libc::SIGINT, libc::SIGTERM, libc::SIGHUP] {
+            libc::sigaddset(&mut set, signal);
+        }
+        let res = libc::pthread_sigmask(libc::SIG_BLOCK, &set, std::ptr::null_mut());
+        if res != 0 {
+            return Err(io::Error::from_raw_os_error(res));
+        }
+        Ok(set)
+    }
+}
+
+fn wait_signal(set: &libc::sigset_t) -> io::Result<libc::c_int> {
+    let mut signal = 0;
+    // SAFETY: set is a valid signal set
+    let res = unsafe { libc::sigwait(set, &mut signal) };
+    if res != 0 {
+        return Err(io::Error::from_raw_os_error(res));
+    }
+    Ok(signal)
+}
+
+fn signal_name(signal: libc::c_int) -> &'static str {
+    match signal {
+        libc::SIGINT => "SIGINT",
+        libc::SIGTERM => "SIGTERM",
+        libc::SIGHUP => "SIGHUP",
+        _ => "signal",
+    }
+}
+
+fn unload(sysfs: &SysfsPath, args: &UnloadArgs) -> Result<()> {
+    let usb_hid = find_hid_devices(sysfs)?;
+
+    let hid_ids = if args.all {
+        pinned_drivers()?
+    } else {
+        let device = args.device.as_ref().unwrap();
+        if let Some(id) = device.to_str().and_then(|s| i32::from_str_radix(s, 16).ok()) {
+            vec![id]
         } else {
-            bail!(format!("Driver already exists, to remove: rm {bpffs_name}"));
+            let device = sysfs
+                .sub(&PathBuf::from(device))?
+                .ok_or_eyre("Device not found")?;
+            let Some(hids) = usb_hid.get(&device) else {
+                bail!("Device does not seem to be a relevant USB device.");
+            };
+            hids.iter()
+                .filter(|(num, ..)| *num == 0)
+                .filter_map(|(_, name, _)| parse_hid_id(name))
+                .collect()
         }
+    };
+
+    if hid_ids.is_empty() && !args.quiet {
+        eprintln!("No drivers loaded");
     }
 
-    if !args.force {
-        let orig_rdesc = hid_dev.read("report_descriptor")?;
+    let hid_bus = sysfs
+        .get_subsystem("hid")?
+        .ok_or_eyre("No hid subsystem found in sysfs")?;
+
+    for hid_id in hid_ids {
+        let bpffs_name = pin_path(hid_id);
+        match std::fs::remove_file(&bpffs_name) {
+            Ok(()) => (),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                bail!("No pinned driver for HID {hid_id:04X}");
+            }
+            Err(e) => Err(e).wrap_err_with(|| format!("Removing {bpffs_name}"))?,
+        }
+        if !args.quiet {
+            eprintln!("Removed {bpffs_name}");
+        }
+
+        let Some((usb, hids)) = usb_hid.iter().find(|(_, hids)| {
+            hids.iter()
+                .any(|(_, name, _)| parse_hid_id(name) == Some(hid_id))
+        }) else {
+            // Unplugged, nothing to restore
+            continue;
+        };
 
-        if orig_rdesc[..3] != [0x06, 0x00, 0xff] {
-            bail!("Found HID device with unexpected descriptor. Is a driver already loaded?");
+        for (num, hid_name, hid) in hids {
+            if *num == 0 || hid.sub("driver")?.is_some() {
+                continue;
+            }
+            rebind(&hid_bus, hid_name, &hid.recover_path()?, args.quiet)?;
+        }
+
+        if args.reset {
+            require_usb(usb).wrap_err("Cannot reset device")?;
+            let mut usbfs = usbfs::Usbfs::open(usb)?;
+            if !args.quiet {
+                eprintln!("Resetting {}", usbfs.path().display());
+            }
+            usbfs.reset().wrap_err("Failed to reset device")?;
         }
     }
 
-    let link = fixup_device(hid_id, &new_rdesc).map_err(|e| {
-        match e.downcast_ref::<libbpf_rs::Error>() {
-            Some(ioe) if ioe.kind() == libbpf_rs::ErrorKind::PermissionDenied => {
-                e.wrap_err("Cannot load BPF (Try running as root?)")
+    Ok(())
+}
+
+fn install_udev(args: &InstallUdevArgs) -> Result<()> {
+    let db = DeviceDb::load()?;
+    let bin = match &args.bin {
+        Some(bin) => bin.clone(),
+        None => std::env::current_exe().wrap_err("Cannot find hid-bpf-uclogic executable")?,
+    };
+    let paths = udev::Paths {
+        bin,
+        huion_switcher: args.huion_switcher.clone(),
+    };
+
+    for (path, text) in udev::files(&db, &paths)? {
+        let path = args.prefix.join(path);
+        if args.dry_run {
+            println!("# {}\n{text}", path.display());
+            continue;
+        }
+
+        std::fs::create_dir_all(path.parent().unwrap())
+            .and_then(|()| std::fs::write(&path, text))
+            .wrap_err_with(|| format!("Writing {}", path.display()))?;
+        eprintln!("Wrote {}", path.display());
+    }
+
+    if !args.dry_run {
+        eprintln!("To apply: udevadm control --reload && systemctl daemon-reload");
+    }
+
+    Ok(())
+}
+
+fn daemon(sysfs: &SysfsPath, db: &DeviceDb, args: &DaemonArgs) -> Result<()> {
+    use daemon::Action;
+
+    find_bpffs()?;
+
+    let mut source = uevent::Netlink::open().wrap_err("Cannot listen for device events")?;
+    if !args.quiet {
+        eprintln!("Waiting for supported devices");
+    }
+
+    daemon::run(sysfs, db, &mut source, |action| match action {
+        Action::Load { usb, hid_id } => {
+            if PathBuf::from(pin_path(*hid_id)).exists() {
+                return Ok(());
+            }
+            load(
+                sysfs,
+                db,
+                &Args {
+                    device: Some(usb.into()),
+                    with_huion_switcher: args.with_huion_switcher.clone(),
+                    device_info: None,
+                    from_env: false,
+                    from_udev_db: false,
+                    force: false,
+                    quiet: args.quiet,
+                    wait: false,
+                    dump_descriptor: false,
+                    dry_run: false,
+                    format: Format::Text,
+                    config: None,
+                },
+            )
+            .wrap_err_with(|| format!("Loading driver for {}", usb.display()))
+        }
+        Action::Unpin(hid_id) => {
+            let bpffs_name = pin_path(*hid_id);
+            match std::fs::remove_file(&bpffs_name) {
+                Ok(()) => {
+                    if !args.quiet {
+                        eprintln!("Removed {bpffs_name}");
+                    }
+                    Ok(())
+                }
+                Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+                Err(e) => Err(e).wrap_err_with(|| format!("Removing {bpffs_name}")),
             }
-            _ => e.wrap_err("Cannot load BPF"),
         }
+    })
+}
+
+fn status(sysfs: &SysfsPath, db: &DeviceDb, args: &StatusArgs) -> Result<()> {
+    let usb_hid = find_hid_devices(sysfs)?;
+
+    let hid_bus = sysfs
+        .get_subsystem("hid")?
+        .ok_or_eyre("No hid subsystem found in sysfs")?;
+    let mut present = Vec::new();
+    for dev in hid_bus.devices()? {
+        let (name, _) = dev?;
+        present.extend(parse_hid_id(&name));
+    }
+
+    let pinned = pinned_drivers().map_err(|e| match e.downcast_ref::<io::Error>() {
+        Some(ioe) if ioe.kind() == io::ErrorKind::PermissionDenied => {
+            e.wrap_err("Cannot list drivers (Try running as root?)")
+        }
+        _ => e,
     })?;
+    let loaded = loaded::loaded_drivers().wrap_err("Cannot query loaded drivers")?;
 
-    if args.wait {
-        eprintln!("Driver loaded, Ctrl-C to terminate and unload");
-        loop {
-            // SAFETY: This function is safe
-            unsafe {
-                libc::pause();
+    let mut hid_ids: Vec<i32> = loaded.iter().map(|d| d.config.hid_id).collect();
+    hid_ids.extend(&pinned);
+    hid_ids.sort();
+    hid_ids.dedup();
+
+    if hid_ids.is_empty() {
+        eprintln!("No drivers loaded");
+    }
+
+    let mut stale = Vec::new();
+    let mut drivers = Vec::new();
+
+    for hid_id in hid_ids {
+        let bpffs_name = pin_path(hid_id);
+        let is_stale = !present.contains(&hid_id);
+        let is_pinned = pinned.contains(&hid_id);
+        if is_stale && is_pinned {
+            stale.push(bpffs_name.clone());
+        }
+
+        let mut record = records::Driver {
+            hid_id: records::hid_id(hid_id),
+            stale: is_stale,
+            pin: is_pinned.then(|| bpffs_name.clone()),
+            link_id: None,
+            struct_ops_map_id: None,
+            link_error: None,
+            syspath: None,
+            firmware: None,
+            protocol: None,
+            matched: None,
+            params: None,
+            params_error: None,
+            prog_ids: Vec::new(),
+            descriptor_size: None,
+        };
+
+        if is_pinned {
+            match libbpf_rs::Link::open(&bpffs_name).map_err(eyre::Report::from) {
+                Ok(link) => match loaded::link_ids(&link) {
+                    Ok(ids) => {
+                        record.link_id = Some(ids.link_id);
+                        record.struct_ops_map_id = Some(ids.map_id);
+                    }
+                    Err(e) => record.link_error = Some(format!("{e:#}")),
+                },
+                Err(e) => record.link_error = Some(format!("cannot open: {e:#}")),
             }
         }
-    } else {
-        { link }
-            .pin(&bpffs_name)
-            .wrap_err("Failed to pin BPF link")?;
-        if !args.quiet {
-            eprintln!("Driver loaded, to unload: rm {bpffs_name}");
+
+        let usb = usb_hid.iter().find(|(_, hids)| {
+            hids.iter()
+                .any(|(num, name, _)| *num == 0 && parse_hid_id(name) == Some(hid_id))
+        });
+        let id = match usb {
+            Some((usb, _)) => {
+                record.syspath = Some(usb.recover_path()?);
+                device_id(usb)?
+            }
+            None => None,
+        };
+
+        if let Some(driver) = loaded.iter().find(|d| d.config.hid_id == hid_id) {
+            let info = &driver.config.info;
+            let entry = db.by_firmware(id, &info.firmware);
+            record.firmware = Some(info.firmware.clone());
+            record.protocol = Some(info.protocol);
+            record.matched = entry.map(records::Match::new);
+            match info.parse() {
+                Ok(mut parsed) => {
+                    if let Some(entry) = entry {
+                        parsed.apply_features(&entry.features);
+                    }
+                    record.params = Some(parsed);
+                }
+                Err(e) => record.params_error = Some(format!("{e:#}")),
+            }
+            record.prog_ids = driver.prog_ids.clone();
+            record.descriptor_size = Some(driver.config.rdesc_size);
         }
+
+        drivers.push(record);
+    }
+
+    match args.format {
+        Format::Text => drivers.iter().for_each(print_driver),
+        Format::Json => records::write(&mut io::stdout().lock(), &drivers)?,
+    }
+
+    if args.remove_stale {
+        for bpffs_name in stale {
+            std::fs::remove_file(&bpffs_name).wrap_err_with(|| format!("Removing {bpffs_name}"))?;
+            eprintln!("Removed {bpffs_name}");
+        }
+    } else if !stale.is_empty() {
+        eprintln!("To remove stale drivers: hid-bpf-uclogic status --remove-stale");
     }
 
     Ok(())
 }
 
+fn print_driver(driver: &records::Driver) {
+    if driver.stale {
+        println!(
+            "- HID {} (stale, HID device no longer exists)",
+            driver.hid_id
+        );
+    } else {
+        println!("- HID {}", driver.hid_id);
+    }
+
+    match (&driver.pin, driver.link_id, driver.struct_ops_map_id) {
+        (Some(pin), Some(link_id), Some(map_id)) => {
+            println!("  pinned {pin}, link {link_id}, struct_ops map {map_id}");
+        }
+        (Some(pin), ..) => {
+            println!(
+                "  pinned {pin}, {}",
+                driver.link_error.as_deref().unwrap_or_default()
+            );
+        }
+        (None, ..) => println!("  not pinned, held by hid-bpf-uclogic --wait"),
+    }
+
+    if let Some(syspath) = &driver.syspath {
+        println!("  syspath {}", syspath.display());
+    }
+
+    let (Some(firmware), Some(protocol)) = (&driver.firmware, driver.protocol) else {
+        println!("  no programs found");
+        return;
+    };
+
+    match &driver.matched {
+        Some(entry) => println!(
+            "  device id {firmware:?} (protocol {protocol}), matched {} ({})",
+            entry.name, entry.features
+        ),
+        None => println!("  device id {firmware:?} (protocol {protocol}), not in device database"),
+    }
+    match (&driver.params, &driver.params_error) {
+        (Some(parsed), _) => println!("  {parsed}"),
+        (None, e) => println!(
+            "  Cannot parse device info: {}",
+            e.as_deref().unwrap_or_default()
+        ),
+    }
+
+    let prog_ids: Vec<String> = driver.prog_ids.iter().map(u32::to_string).collect();
+    println!(
+        "  programs {}, descriptor {} bytes",
+        prog_ids.join(", "),
+        driver.descriptor_size.unwrap_or_default()
+    );
+}
+
+fn pin_path(hid_id: i32) -> String {
+    format!("/sys/fs/bpf/hid-bpf-uclogic-{hid_id:04X}")
+}
+
+/// HID ids of drivers pinned in bpffs
+fn pinned_drivers() -> Result<Vec<i32>> {
+    let mut hid_ids = Vec::new();
+    for ent in std::fs::read_dir(find_bpffs()?)? {
+        let name = ent?.file_name();
+        let hid_id = name
+            .to_str()
+            .and_then(|n| n.strip_prefix("hid-bpf-uclogic-"))
+            .and_then(|id| i32::from_str_radix(id, 16).ok());
+        hid_ids.extend(hid_id);
+    }
+    hid_ids.sort();
+    Ok(hid_ids)
+}
+
 fn parse_hid_id(name: &OsStr) -> Option<i32> {
     let id = name.to_str()?.split('.').last()?;
     Some(i32::from_str_radix(id, 16).ok()?)
@@ -289,8 +1361,33 @@ fn find_bpffs() -> Result<PathBuf> {
     Ok("/sys/fs/bpf".into())
 }
 
-fn call_huion_switcher(device: &Path, huion_switcher: OsString, quiet: bool) -> Result<String> {
-    let mut command = std::process::Command::new(&huion_switcher);
+fn read_device_info(device: &SysfsPath, quiet: bool) -> Result<descriptor::DeviceInfo> {
+    require_usb(device)?;
+    let mut usb = usbfs::Usbfs::open(device)?;
+    if !quiet {
+        eprintln!(
+            r#"
+!!! Default device functionality will be disabled, unplug and replug to reset
+"#
+        );
+        eprintln!("Reading string descriptors from {}", usb.path().display());
+    }
+    usbfs::read_device_info(&mut usb)
+}
+
+/// Device info and resetting need USB, other buses have no string descriptors
+fn require_usb(device: &SysfsPath) -> Result<()> {
+    match device_bus(device)? {
+        Some(Bus::Usb) => Ok(()),
+        Some(bus) => bail!(
+            "{bus} devices have no USB string descriptors, use --device-info or --from-env"
+        ),
+        None => bail!("Not a USB device"),
+    }
+}
+
+fn call_huion_switcher(device: &Path, huion_switcher: &OsStr, quiet: bool) -> Result<String> {
+    let mut command = std::process::Command::new(huion_switcher);
     command
         .arg(device)
         .stdout(std::process::Stdio::piped())
@@ -317,70 +1414,239 @@ fn call_huion_switcher(device: &Path, huion_switcher: OsString, quiet: bool) ->
     Ok(String::from_utf8(output.stdout)?)
 }
 
-fn list_devices(sysfs: &SysfsPath, show_all: bool) -> Result<()> {
-    let devs = find_usb_hid(sysfs)?;
-    for (usb, hids) in devs {
-        if !show_all && !usb_supported(&usb).unwrap_or_default() {
+fn list_devices(
+    sysfs: &SysfsPath,
+    db: &DeviceDb,
+    show_all: bool,
+    format: Format,
+    out: &mut impl io::Write,
+) -> Result<()> {
+    let mut devs = Vec::new();
+    for (usb, hids) in find_hid_devices(sysfs)? {
+        if !show_all && !device_supported(&usb, db).unwrap_or_default() {
             continue;
         }
+        devs.push(device_record(&usb, &hids, db)?);
+    }
+    devs.sort_by(|a, b| a.syspath.cmp(&b.syspath));
 
-        print_usb_device(&usb)?;
+    if format == Format::Json {
+        return records::write(out, &devs);
+    }
 
-        for (num, name, hid) in hids {
-            let Some(id) = parse_hid_id(&name) else {
-                continue;
-            };
-            println!(
-                "  - .{num} HID {id:04X} {hid}",
-                hid = hid.recover_path()?.display()
-            );
+    for dev in devs {
+        print_device(&dev, out)?;
+
+        if !dev.candidates.is_empty() {
+            writeln!(out, "  candidates {}", dev.candidates.join(", "))?;
+        }
+
+        for interface in dev.interfaces {
+            writeln!(
+                out,
+                "  - .{} HID {} {}",
+                interface.number,
+                interface.hid_id,
+                interface.syspath.display()
+            )?;
+
+            let mut state = vec![if interface.vendor {
+                format!("vendor interface, {}", interface.kind)
+            } else {
+                format!("compat {}", interface.kind)
+            }];
+            state.push(match &interface.driver {
+                Some(driver) => format!("driver {driver}"),
+                None => "no driver".to_owned(),
+            });
+            if let Some(pin) = &interface.pin {
+                state.push(format!("pinned {pin}"));
+            }
+            let nodes = interface.hidraw.iter().chain(&interface.input);
+            state.extend(nodes.map(|n| n.display().to_string()));
+            writeln!(out, "    {}", state.join(", "))?;
         }
     }
     Ok(())
 }
 
-fn print_usb_device(usb: &SysfsPath) -> Result<()> {
-    let usb_path = usb.recover_path()?;
-    let base = usb_path.file_name().unwrap();
-    let prop = |name, default: &str| {
-        usb.property_trim(name)
-            .map(|p| p.unwrap_or(default.to_owned()))
+fn device_record(
+    usb: &SysfsPath,
+    hids: &[(u8, OsString, SysfsPath)],
+    db: &DeviceDb,
+) -> Result<records::Device> {
+    let candidates = match device_id(usb)? {
+        Some(id) => db.by_id(id).map(|e| e.name.clone()).collect(),
+        None => Vec::new(),
     };
-    let vid = prop("idVendor", "????")?;
-    let pid = prop("idProduct", "????")?;
-    let manufacturer = prop("manufacturer", "(Unknown manufacturer)")?;
-    let product = prop("product", "(Unknown product)")?;
-    println!(
-        "- USB {base} {manufacturer} {product} ({vid}:{pid})
+
+    let mut interfaces = Vec::new();
+    for (num, name, hid) in hids {
+        let Some(id) = parse_hid_id(name) else {
+            continue;
+        };
+        let driver = match hid.sub("driver")? {
+            Some(driver) => driver
+                .recover_path()?
+                .file_name()
+                .map(|n| n.to_string_lossy().into_owned()),
+            None => None,
+        };
+        let kind = match hid.read("report_descriptor") {
+            Ok(rdesc) => classify(&rdesc),
+            Err(_) => InterfaceKind::Unknown,
+        };
+        let pin = pin_path(id);
+        let pin = (*num == 0 && Path::new(&pin).exists()).then_some(pin);
+
+        let hidraw = hid.list("hidraw")?;
+        let mut input = Vec::new();
+        for dev in hid.list("input")? {
+            let events = hid.list(&format!("input/{}", dev.to_string_lossy()))?;
+            input.extend(
+                events
+                    .into_iter()
+                    .filter(|n| n.to_string_lossy().starts_with("event"))
+                    .map(|n| Path::new("/dev/input").join(n)),
+            );
+        }
+
+        interfaces.push(records::Interface {
+            number: *num,
+            hid_id: records::hid_id(id),
+            syspath: hid.recover_path()?,
+            vendor: *num == 0,
+            kind,
+            driver,
+            pin,
+            hidraw: hidraw.iter().map(|n| Path::new("/dev").join(n)).collect(),
+            input,
+        });
+    }
+
+    let bus = device_bus(usb)?;
+    let (vid, pid, manufacturer, product) = if bus == Some(Bus::Usb) {
+        (
+            usb.property_trim("idVendor")?,
+            usb.property_trim("idProduct")?,
+            usb.property_trim("manufacturer")?,
+            usb.property_trim("product")?,
+        )
+    } else {
+        // No string descriptors, but the kernel has a name
+        let id = device_id(usb)?;
+        (
+            id.map(|id| format!("{:04x}", id.id.vid)),
+            id.map(|id| format!("{:04x}", id.id.pid)),
+            None,
+            hid::uevent_property(usb, "HID_NAME")?,
+        )
+    };
+
+    Ok(records::Device {
+        syspath: usb.recover_path()?,
+        bus,
+        vid,
+        pid,
+        manufacturer,
+        product,
+        candidates,
+        interfaces,
+    })
+}
+
+/// What an interface is for, from its report descriptor
+fn classify(rdesc: &[u8]) -> InterfaceKind {
+    use rdesc::{desktop, page};
+
+    // Only our descriptors have just the reports of the BPF program
+    let has_reports = decode::layout(rdesc).is_ok_and(|r| !r.is_empty());
+    if has_reports && reports::check(rdesc).is_ok() {
+        return InterfaceKind::FixedUp;
+    }
+
+    let usage = match decode::application_usage(rdesc) {
+        Ok(Some(usage)) => usage,
+        Ok(None) => return InterfaceKind::Other,
+        Err(_) => return InterfaceKind::Unknown,
+    };
+    match ((usage >> 16) as u16, usage as u16) {
+        (page::VENDOR, _) => InterfaceKind::Vendor,
+        (page::DIGITIZERS, _) => InterfaceKind::Digitizer,
+        (page::GENERIC_DESKTOP, desktop::KEYBOARD | desktop::KEYPAD) => InterfaceKind::Keyboard,
+        (page::GENERIC_DESKTOP, desktop::MOUSE | desktop::POINTER) => InterfaceKind::Mouse,
+        _ => InterfaceKind::Other,
+    }
+}
+
+fn print_device(dev: &records::Device, out: &mut impl io::Write) -> Result<()> {
+    writeln!(
+        out,
+        "- {bus} {base} {manufacturer} {product} ({vid}:{pid})
   syspath {usb}",
-        base = base.to_string_lossy(),
-        usb = usb_path.to_string_lossy(),
-    );
+        bus = dev.bus.map_or("Unknown bus".to_owned(), |b| b.to_string()),
+        base = dev.syspath.file_name().unwrap().to_string_lossy(),
+        manufacturer = dev
+            .manufacturer
+            .as_deref()
+            .unwrap_or("(Unknown manufacturer)"),
+        product = dev.product.as_deref().unwrap_or("(Unknown product)"),
+        vid = dev.vid.as_deref().unwrap_or("????"),
+        pid = dev.pid.as_deref().unwrap_or("????"),
+        usb = dev.syspath.display(),
+    )?;
     Ok(())
 }
 
-fn usb_supported(device: &SysfsPath) -> Result<bool> {
-    if device.subsystem()? != Some("usb".to_owned()) {
-        return Ok(false);
+/// Bus, vendor and product of a device from [`find_hid_devices`]
+///
+/// Bluetooth and virtual devices have ids of their own, which are matched
+/// against the `hid` ids in the device database rather than the USB ones.
+fn device_id(device: &SysfsPath) -> Result<Option<hid::HidId>> {
+    match device.subsystem()?.as_deref() {
+        Some("usb") => (),
+        Some("hid") => return hid::HidId::read(device),
+        _ => return Ok(None),
     }
 
     let Some(vid) = device.property_trim("idVendor")? else {
-        return Ok(false);
+        return Ok(None);
     };
-    let Ok(vid) = u32::from_str_radix(&vid, 16) else {
-        return Ok(false);
+    let Ok(vid) = u16::from_str_radix(&vid, 16) else {
+        return Ok(None);
     };
     let Some(pid) = device.property_trim("idProduct")? else {
-        return Ok(false);
+        return Ok(None);
+    };
+    let Ok(pid) = u16::from_str_radix(&pid, 16) else {
+        return Ok(None);
     };
-    let Ok(pid) = u32::from_str_radix(&pid, 16) else {
+
+    Ok(Some(hid::HidId::usb(UsbId { vid, pid })))
+}
+
+fn device_bus(device: &SysfsPath) -> Result<Option<Bus>> {
+    match device.subsystem()?.as_deref() {
+        Some("usb") => Ok(Some(Bus::Usb)),
+        Some("hid") => Ok(hid::HidId::read(device)?.map(|h| h.bus)),
+        _ => Ok(None),
+    }
+}
+
+fn device_supported(device: &SysfsPath, db: &DeviceDb) -> Result<bool> {
+    let Some(id) = device_id(device)? else {
         return Ok(false);
     };
 
-    Ok(SUPPORTED_VID_PID.contains(&(vid, pid)))
+    Ok(db.by_id(id).next().is_some())
 }
 
-fn find_usb_hid<'a>(
+/// HID devices, grouped by the device they belong to
+///
+/// USB HID devices are grouped under their USB device, numbered by
+/// interface. Other HID devices have no such parent, and stand for
+/// themselves as number 0.
+fn find_hid_devices<'a>(
     sysfs: &SysfsPath<'a>,
 ) -> Result<HashMap<SysfsPath<'a>, Vec<(u8, OsString, SysfsPath<'a>)>>> {
     let sys_usb = sysfs
@@ -402,6 +1668,14 @@ fn find_usb_hid<'a>(
     for dev in sys_hid.devices()? {
         let (name, dev) = dev?;
 
+        // Bluetooth devices may well be under a USB adapter
+        let bus = hid::HidId::read(&dev)?.map(|h| h.bus);
+        if bus.is_some_and(|b| b != Bus::Usb) {
+            let key = dev.sub(".")?.unwrap();
+            devices.insert(key, vec![(0, name, dev)]);
+            continue;
+        }
+
         if let Some(parent) = dev.find_map_parent(|p| Ok(devices.contains_key(&p).then_some(p)))? {
             let Some(interface_num) = dev.find_map_parent(|p| {
                 if p.subsystem()? != Some("usb".to_owned()) {
@@ -425,29 +1699,328 @@ fn find_usb_hid<'a>(
     // Make it look better
     devices.values_mut().for_each(|x| x.sort_by_key(|p| p.0));
 
-    // Only keep USB devices with HID children
+    // Only keep devices with HID children
     devices.retain(|_, devs| !devs.is_empty());
     Ok(devices)
 }
 
-fn fixup_device(hid_id: i32, rdesc: &[u8]) -> Result<Link> {
+/// Largest report descriptor the kernel accepts
+const HID_MAX_DESCRIPTOR_SIZE: usize = 4096;
+
+/// Open the BPF object and fill in its configuration, ready to load
+fn open_bpf(config: &loaded::Config, rdesc: &[u8]) -> Result<OpenObject> {
     let mut open_obj = ObjectBuilder::default()
         .open_memory(include_bytes!(concat!(env!("OUT_DIR"), "/uclogic.bpf.o")))?;
-    let mut config = open_obj
+    let mut config_map = open_obj
         .maps_mut()
         .find(|m| m.name() == ".rodata.uclogic_config")
         .unwrap();
-    let (conf_size, conf_data) = config.initial_value_mut().unwrap().split_at_mut(4);
-    conf_size.copy_from_slice(&u32::try_from(rdesc.len()).unwrap().to_ne_bytes());
-    conf_data[..rdesc.len()].copy_from_slice(rdesc);
+    config.write(config_map.initial_value_mut().unwrap())?;
+    let mut rdesc_map = open_obj
+        .maps_mut()
+        .find(|m| m.name() == ".rodata.uclogic_rdesc")
+        .unwrap();
+    rdesc_map.set_value_size(u32::try_from(rdesc.len()).unwrap())?;
+    rdesc_map.initial_value_mut().unwrap().copy_from_slice(rdesc);
     let mut ops = open_obj
         .maps_mut()
         .find(|m| m.name() == "uclogic_ops")
         .unwrap();
     let ops = ops.initial_value_mut().unwrap();
-    ops[..4].copy_from_slice(&hid_id.to_ne_bytes());
-    let mut obj = open_obj.load()?;
+    ops[..4].copy_from_slice(&config.hid_id.to_ne_bytes());
+    Ok(open_obj)
+}
+
+fn fixup_device(config: &loaded::Config, rdesc: &[u8]) -> Result<Link> {
+    let mut obj = open_bpf(config, rdesc)?.load()?;
     let mut ops = obj.maps_mut().find(|m| m.name() == "uclogic_ops").unwrap();
     let link = ops.attach_struct_ops()?;
     Ok(link)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixture::{FakeSysfs, UsbDevice};
+
+    /// Supported tablets, including two with the same USB id, a device
+    /// that is not a tablet, and a v1 tablet missing from the database
+    fn fake_sysfs(name: &str) -> FakeSysfs {
+        let fake = FakeSysfs::new(name);
+        fake.usb("1-4", UsbDevice::GAOMON_M7);
+        fake.usb("1-5", UsbDevice::HUION_HC16);
+        fake.usb("1-6", UsbDevice::HUION_DIAL_2);
+        fake.usb("1-7", UsbDevice::RECEIVER);
+        fake.usb("1-8", UsbDevice::HUION_H610_PRO);
+        fake
+    }
+
+    #[test]
+    fn test_find_hid_devices() {
+        let fake = fake_sysfs("find-usb-hid");
+        let sysfs = fake.sysfs();
+        let sysfs = sysfs.root().unwrap();
+        let db = DeviceDb::load_from(&[]).unwrap();
+
+        let mut found: Vec<_> = find_hid_devices(&sysfs)
+            .unwrap()
+            .into_iter()
+            .map(|(usb, hids)| {
+                let hids = hids
+                    .into_iter()
+                    .map(|(num, name, hid)| (num, name, hid.sub("driver").unwrap().is_some()))
+                    .collect();
+                (
+                    usb.recover_path().unwrap(),
+                    device_supported(&usb, &db).unwrap(),
+                    hids,
+                )
+            })
+            .collect();
+        found.sort();
+
+        // Interfaces other than 0 are bound to hid-generic
+        let hid = |num: u8, name: &str| (num, OsString::from(name), num != 0);
+
+        // The root hub has no HID devices of its own
+        assert_eq!(
+            found,
+            [
+                (
+                    fake.path("1-4"),
+                    true,
+                    vec![
+                        hid(0, "0003:256C:0064.004C"),
+                        hid(1, "0003:256C:0064.004D"),
+                        hid(2, "0003:256C:0064.004E"),
+                    ]
+                ),
+                (
+                    fake.path("1-5"),
+                    true,
+                    vec![
+                        hid(0, "0003:256C:0064.0052"),
+                        hid(1, "0003:256C:0064.0053"),
+                        hid(2, "0003:256C:0064.0054"),
+                    ]
+                ),
+                (
+                    fake.path("1-6"),
+                    true,
+                    vec![
+                        hid(0, "0003:256C:0060.0058"),
+                        hid(1, "0003:256C:0060.0059"),
+                        hid(2, "0003:256C:0060.005A"),
+                        hid(3, "0003:256C:0060.005B"),
+                    ]
+                ),
+                (
+                    fake.path("1-7"),
+                    false,
+                    vec![hid(0, "0003:046D:C52B.005E"), hid(1, "0003:046D:C52B.005F")]
+                ),
+                (
+                    fake.path("1-8"),
+                    false,
+                    vec![hid(0, "0003:256C:006E.0062"), hid(1, "0003:256C:006E.0063")]
+                ),
+            ]
+        );
+
+        let root_hub = sysfs.sub(fixture::USB).unwrap().unwrap();
+        assert!(!device_supported(&root_hub, &db).unwrap());
+    }
+
+    #[test]
+    fn test_classify() {
+        use fixture::{KEYBOARD_RDESC, MOUSE_RDESC, PEN_RDESC, V1_PEN_RDESC, VENDOR_RDESC};
+
+        let info = include_str!("../fixtures/device-info/gaomon-m7.txt");
+        let info = descriptor::DeviceInfo::from_str(info).unwrap();
+        let fixed = info.parse().unwrap().descriptor().unwrap();
+
+        assert_eq!(classify(VENDOR_RDESC), InterfaceKind::Vendor);
+        assert_eq!(classify(PEN_RDESC), InterfaceKind::Digitizer);
+        assert_eq!(classify(V1_PEN_RDESC), InterfaceKind::Digitizer);
+        assert_eq!(classify(KEYBOARD_RDESC), InterfaceKind::Keyboard);
+        assert_eq!(classify(MOUSE_RDESC), InterfaceKind::Mouse);
+        assert_eq!(classify(&fixed), InterfaceKind::FixedUp);
+        assert_eq!(classify(&[]), InterfaceKind::Other);
+        assert_eq!(classify(&[0x05]), InterfaceKind::Unknown);
+    }
+
+    #[test]
+    fn test_list_devices() {
+        let fake = fake_sysfs("list-devices");
+        let sysfs = fake.sysfs();
+        let sysfs = sysfs.root().unwrap();
+        let db = DeviceDb::load_from(&[]).unwrap();
+
+        let list = |show_all, format| {
+            let mut out = Vec::new();
+            list_devices(&sysfs, &db, show_all, format, &mut out).unwrap();
+            String::from_utf8(out)
+                .unwrap()
+                .replace(fake.root.to_str().unwrap(), "/sys")
+        };
+
+        let all = list(true, Format::Text);
+        insta::assert_snapshot!(all);
+        // Only the receiver and the v1 tablet are left out
+        let supported = list(false, Format::Text);
+        assert_eq!(supported, all[..all.find("- USB 1-7").unwrap()]);
+
+        insta::assert_snapshot!("list_devices_json", list(false, Format::Json));
+    }
+
+    #[test]
+    fn test_supported_devices() {
+        let fake = fake_sysfs("supported-devices");
+        let sysfs = fake.sysfs();
+        let sysfs = sysfs.root().unwrap();
+        let db = DeviceDb::load_from(&[]).unwrap();
+
+        let usb_hid = find_hid_devices(&sysfs).unwrap();
+        let devices = supported_devices(&usb_hid, &db).unwrap();
+        let paths: Vec<_> = devices.into_iter().map(|(path, _)| path).collect();
+        // Not the receiver
+        assert_eq!(
+            paths,
+            [fake.path("1-4"), fake.path("1-5"), fake.path("1-6")]
+        );
+    }
+
+    #[test]
+    fn test_dry_run() {
+        let fake = FakeSysfs::new("dry-run");
+        fake.usb("1-4", UsbDevice::GAOMON_M7);
+        let sysfs = fake.sysfs();
+        let sysfs = sysfs.root().unwrap();
+        let db = DeviceDb::load_from(&[]).unwrap();
+        let usb_hid = find_hid_devices(&sysfs).unwrap();
+        let (usb, hids) = usb_hid
+            .iter()
+            .find(|(usb, _)| usb.recover_path().unwrap() == fake.path("1-4"))
+            .unwrap();
+
+        let info_path = fake.root.join("device-info.txt");
+        let dry_run = |info: &str| {
+            std::fs::write(&info_path, info).unwrap();
+            let args = Args {
+                device: None,
+                with_huion_switcher: None,
+                device_info: Some(info_path.clone().into()),
+                from_env: false,
+                from_udev_db: false,
+                force: false,
+                quiet: true,
+                wait: false,
+                dump_descriptor: false,
+                dry_run: true,
+                format: Format::Json,
+                config: None,
+            };
+            let mut records = Vec::new();
+            let user_config = config::UserConfig::default();
+            let result = load_device(&sysfs, &db, &user_config, &args, usb, hids, &mut records);
+            (result.is_ok(), records.pop().unwrap().plan.unwrap())
+        };
+        // These depend on the machine running the tests, not the fixture
+        let failed = |plan: &records::Plan| {
+            let machine = [
+                "bpffs is mounted",
+                "No driver is pinned for the device",
+                "BPF object accepts the config and descriptor",
+            ];
+            let failed = plan.checks.iter().filter(|c| c.error.is_some());
+            failed
+                .map(|c| c.check.as_str())
+                .filter(|c| !machine.contains(c))
+                .map(str::to_owned)
+                .collect::<Vec<_>>()
+        };
+
+        let info = include_str!("../fixtures/device-info/gaomon-m7.txt");
+        let (_, plan) = dry_run(info);
+        let checks: Vec<_> = plan.checks.iter().map(|c| c.check.as_str()).collect();
+        assert_eq!(
+            checks,
+            [
+                "bpffs is mounted",
+                "USB id is in the device database",
+                "Device id is in the device database",
+                "Descriptor matches the reports of the BPF program",
+                "Descriptor size is accepted by the kernel",
+                "No driver is pinned for the device",
+                "Vendor interface has its original descriptor",
+                "BPF object accepts the config and descriptor",
+            ]
+        );
+        assert_eq!(failed(&plan), Vec::<String>::new());
+        let root = fake.root.to_str().unwrap();
+        let steps: Vec<_> = plan.steps.iter().map(|s| s.replace(root, "/sys")).collect();
+        assert_eq!(
+            steps,
+            [
+                "Unbind compatibility device /sys/devices/pci0000:00/0000:00:14.0/usb1/1-4/1-4:1.2/0003:256C:0064.004E",
+                "Unbind compatibility device /sys/devices/pci0000:00/0000:00:14.0/usb1/1-4/1-4:1.1/0003:256C:0064.004D",
+                "Load BPF for HID 004C, with the 215 byte descriptor below",
+                "Check that HID 004C has the new descriptor and input devices",
+                "Pin at /sys/fs/bpf/hid-bpf-uclogic-004C",
+            ]
+        );
+
+        // Still planned in full, but fails
+        let (ok, plan) = dry_run(&info.replace("GM001_T207_210524", "GM001_T999_000000"));
+        assert!(!ok);
+        assert_eq!(failed(&plan), ["Device id is in the device database"]);
+        assert_eq!(plan.steps.len(), 5);
+    }
+
+    #[test]
+    fn test_bluetooth() {
+        let fake = fixture::FakeSysfs::new("bluetooth");
+        fake.usb("1-4", fixture::UsbDevice::RECEIVER);
+        let bt = fake.bluetooth("1-10", fixture::UsbDevice::GAOMON_M7);
+        let sysfs = fake.sysfs();
+        let sysfs = sysfs.root().unwrap();
+
+        // Supported only by its own id, not the USB one
+        let builtin = DeviceDb::load_from(&[]).unwrap();
+        let dir = fake.root.join("db");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("gaomon-m7-bluetooth.toml"),
+            r#"
+            name = "Gaomon M7 (Bluetooth)"
+            hid = ["0005:256c:0064"]
+            firmware = ["GM001_T207_*"]
+            "#,
+        )
+        .unwrap();
+        let db = DeviceDb::load_from(&[dir]).unwrap();
+
+        // Standalone, not under the USB adapter
+        let found = find_hid_devices(&sysfs).unwrap();
+        let (dev, hids) = found
+            .iter()
+            .find(|(dev, _)| dev.recover_path().unwrap() == bt)
+            .unwrap();
+        assert_eq!(hids.len(), 1);
+        assert_eq!(device_bus(dev).unwrap(), Some(Bus::Bluetooth));
+        assert!(!device_supported(dev, &builtin).unwrap());
+        assert!(device_supported(dev, &db).unwrap());
+        assert!(require_usb(dev).is_err());
+
+        let selector = selector::Selector::parse(OsStr::new("hid:004C")).unwrap();
+        let selected = selector::resolve(&sysfs, &db, &selector).unwrap();
+        assert_eq!(selected.recover_path().unwrap(), bt);
+
+        let mut out = Vec::new();
+        list_devices(&sysfs, &db, false, Format::Text, &mut out).unwrap();
+        let out = String::from_utf8(out)
+            .unwrap()
+            .replace(fake.root.to_str().unwrap(), "/sys");
+        insta::assert_snapshot!(out);
+    }
+}
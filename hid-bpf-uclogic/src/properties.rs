@@ -0,0 +1,92 @@
+//! Device info from udev properties, for when huion-switcher has already run
+//! from a udev rule
+//!
+//! Properties are found either in the environment of a program run by udev,
+//! or in the udev database afterwards.
+
+use std::{
+    ffi::OsString,
+    path::{Path, PathBuf},
+};
+
+use eyre::{Context, Result, eyre};
+
+use crate::{descriptor::DeviceInfo, sysfs::SysfsPath};
+
+const UDEV_DB: &str = "/run/udev/data";
+
+/// From the environment, as set by udev for `RUN` and `IMPORT{program}`
+pub(crate) fn from_env() -> Result<DeviceInfo> {
+    from_env_vars(std::env::vars_os())
+}
+
+fn from_env_vars(vars: impl IntoIterator<Item = (OsString, OsString)>) -> Result<DeviceInfo> {
+    // Unrelated variables need not be UTF-8
+    let vars: Vec<(String, String)> = vars
+        .into_iter()
+        .filter_map(|(k, v)| Some((k.into_string().ok()?, v.into_string().ok()?)))
+        .collect();
+    DeviceInfo::from_properties(vars.iter().map(|(k, v)| (k.as_str(), v.as_str())))
+}
+
+/// From the udev database entry of a device
+pub(crate) fn from_udev_db(device: &SysfsPath) -> Result<DeviceInfo> {
+    let path = db_path(Path::new(UDEV_DB), device)?;
+    let text = std::fs::read_to_string(&path)
+        .wrap_err_with(|| format!("Reading udev database entry {}", path.display()))?;
+    DeviceInfo::from_properties(parse_db(&text))
+        .wrap_err_with(|| format!("In {} (Was huion-switcher run by udev?)", path.display()))
+}
+
+/// Entries are named after the device number, or the subsystem and name for
+/// devices without one
+fn db_path(dir: &Path, device: &SysfsPath) -> Result<PathBuf> {
+    let name = match device.property_trim("dev")? {
+        Some(dev) => format!("c{dev}"),
+        None => {
+            let subsystem = device
+                .subsystem()?
+                .ok_or_else(|| eyre!("Device has no subsystem"))?;
+            let path = device.recover_path()?;
+            let sysname = path.file_name().unwrap().to_string_lossy();
+            format!("+{subsystem}:{sysname}")
+        }
+    };
+    Ok(dir.join(name))
+}
+
+/// Properties are the `E:KEY=value` lines
+fn parse_db(text: &str) -> impl Iterator<Item = (&str, &str)> {
+    text.lines()
+        .filter_map(|l| l.strip_prefix("E:"))
+        .filter_map(|l| l.split_once('='))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn expected() -> DeviceInfo {
+        DeviceInfo::from_str(include_str!("../fixtures/device-info/gaomon-m7.txt")).unwrap()
+    }
+
+    #[test]
+    fn test_udev_db() {
+        let text = include_str!("../fixtures/udev-db/gaomon-m7");
+        let info = DeviceInfo::from_properties(parse_db(text)).unwrap();
+        assert_eq!(info, expected());
+
+        let err = DeviceInfo::from_properties(parse_db("E:ID_BUS=usb\nG:systemd\n")).unwrap_err();
+        assert!(format!("{err:#}").contains("No HUION_FIRMWARE_ID found"));
+    }
+
+    #[test]
+    fn test_env() {
+        let text = include_str!("../fixtures/udev-env/gaomon-m7");
+        let vars = text
+            .lines()
+            .filter_map(|l| l.split_once('='))
+            .map(|(k, v)| (k.into(), v.into()));
+        assert_eq!(from_env_vars(vars).unwrap(), expected());
+    }
+}
@@ -0,0 +1,287 @@
+//! Builder for HID report descriptors
+//!
+//! Only short items are supported. See the HID specification, section 6.2.2,
+//! for the item format.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub(crate) enum ItemType {
+    Main = 0,
+    Global = 1,
+    Local = 2,
+    Reserved = 3,
+}
+
+/// Item data, encoded in the shortest form that fits unless a width is given
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Data {
+    Signed(i32),
+    Unsigned(u32),
+    /// Always two bytes
+    U16(u16),
+    /// Always four bytes
+    U32(u32),
+}
+
+/// Collection types
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub(crate) enum Collection {
+    Physical = 0x00,
+    Application = 0x01,
+}
+
+/// Bits of Input, Output and Feature items
+pub(crate) mod flags {
+    pub(crate) const CONSTANT: u8 = 1 << 0;
+    pub(crate) const VARIABLE: u8 = 1 << 1;
+    pub(crate) const RELATIVE: u8 = 1 << 2;
+}
+
+pub(crate) mod page {
+    pub(crate) const GENERIC_DESKTOP: u16 = 0x01;
+    pub(crate) const KEYBOARD: u16 = 0x07;
+    pub(crate) const LED: u16 = 0x08;
+    pub(crate) const BUTTON: u16 = 0x09;
+    pub(crate) const CONSUMER: u16 = 0x0c;
+    pub(crate) const DIGITIZERS: u16 = 0x0d;
+    /// Vendor defined pages are `0xff00..=0xffff`
+    pub(crate) const VENDOR: u16 = 0xff00;
+}
+
+pub(crate) mod desktop {
+    pub(crate) const POINTER: u16 = 0x01;
+    pub(crate) const MOUSE: u16 = 0x02;
+    pub(crate) const JOYSTICK: u16 = 0x04;
+    pub(crate) const GAME_PAD: u16 = 0x05;
+    pub(crate) const KEYBOARD: u16 = 0x06;
+    pub(crate) const KEYPAD: u16 = 0x07;
+    pub(crate) const X: u16 = 0x30;
+    pub(crate) const Y: u16 = 0x31;
+    pub(crate) const Z: u16 = 0x32;
+    pub(crate) const RX: u16 = 0x33;
+    pub(crate) const RY: u16 = 0x34;
+    pub(crate) const RZ: u16 = 0x35;
+    pub(crate) const DIAL: u16 = 0x37;
+    pub(crate) const WHEEL: u16 = 0x38;
+}
+
+pub(crate) mod consumer {
+    pub(crate) const CONSUMER_CONTROL: u16 = 0x01;
+    pub(crate) const VOLUME_INCREMENT: u16 = 0xe9;
+    pub(crate) const VOLUME_DECREMENT: u16 = 0xea;
+    pub(crate) const AC_PAN: u16 = 0x0238;
+}
+
+pub(crate) mod digitizers {
+    pub(crate) const DIGITIZER: u16 = 0x01;
+    pub(crate) const PEN: u16 = 0x02;
+    pub(crate) const STYLUS: u16 = 0x20;
+    pub(crate) const FINGER: u16 = 0x22;
+    pub(crate) const TIP_PRESSURE: u16 = 0x30;
+    pub(crate) const BARREL_PRESSURE: u16 = 0x31;
+    pub(crate) const IN_RANGE: u16 = 0x32;
+    pub(crate) const TABLET_FUNCTION_KEYS: u16 = 0x39;
+    pub(crate) const INVERT: u16 = 0x3c;
+    pub(crate) const X_TILT: u16 = 0x3d;
+    pub(crate) const Y_TILT: u16 = 0x3e;
+    pub(crate) const TIP_SWITCH: u16 = 0x42;
+    pub(crate) const BARREL_SWITCH: u16 = 0x44;
+    pub(crate) const ERASER: u16 = 0x45;
+    pub(crate) const SECONDARY_BARREL_SWITCH: u16 = 0x5a;
+}
+
+pub(crate) mod unit {
+    /// English Linear, inches
+    pub(crate) const INCH: u32 = 0x13;
+    /// English Rotation, degrees
+    pub(crate) const DEGREE: u32 = 0x14;
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct Builder {
+    bytes: Vec<u8>,
+}
+
+impl From<i32> for Data {
+    fn from(value: i32) -> Self {
+        Data::Signed(value)
+    }
+}
+
+impl Data {
+    fn encode(self) -> Vec<u8> {
+        match self {
+            Data::Signed(v) => {
+                if let Ok(v) = i8::try_from(v) {
+                    v.to_le_bytes().to_vec()
+                } else if let Ok(v) = i16::try_from(v) {
+                    v.to_le_bytes().to_vec()
+                } else {
+                    v.to_le_bytes().to_vec()
+                }
+            }
+            Data::Unsigned(v) => {
+                if let Ok(v) = u8::try_from(v) {
+                    v.to_le_bytes().to_vec()
+                } else if let Ok(v) = u16::try_from(v) {
+                    v.to_le_bytes().to_vec()
+                } else {
+                    v.to_le_bytes().to_vec()
+                }
+            }
+            Data::U16(v) => v.to_le_bytes().to_vec(),
+            Data::U32(v) => v.to_le_bytes().to_vec(),
+        }
+    }
+}
+
+impl Builder {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    pub(crate) fn finish(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.bytes)
+    }
+
+    fn item(&mut self, item_type: ItemType, tag: u8, data: Option<Data>) -> &mut Self {
+        let data = data.map_or(vec![], Data::encode);
+        let size = match data.len() {
+            0 => 0,
+            1 => 1,
+            2 => 2,
+            4 => 3,
+            _ => unreachable!(),
+        };
+        self.bytes.push(tag << 4 | (item_type as u8) << 2 | size);
+        self.bytes.extend(data);
+        self
+    }
+
+    fn main(&mut self, tag: u8, data: Option<Data>) -> &mut Self {
+        self.item(ItemType::Main, tag, data)
+    }
+
+    fn global(&mut self, tag: u8, data: Data) -> &mut Self {
+        self.item(ItemType::Global, tag, Some(data))
+    }
+
+    fn local(&mut self, tag: u8, data: Data) -> &mut Self {
+        self.item(ItemType::Local, tag, Some(data))
+    }
+
+    pub(crate) fn input(&mut self, flags: u8) -> &mut Self {
+        self.main(0x8, Some(Data::Unsigned(flags.into())))
+    }
+
+    /// Emit a collection, with items added by `f` inside it
+    pub(crate) fn collection(&mut self, kind: Collection, f: impl FnOnce(&mut Self)) -> &mut Self {
+        self.main(0xa, Some(Data::Unsigned(kind as u32)));
+        f(self);
+        self.main(0xc, None)
+    }
+
+    pub(crate) fn usage_page(&mut self, page: u16) -> &mut Self {
+        self.global(0x0, Data::Unsigned(page.into()))
+    }
+
+    pub(crate) fn logical_minimum(&mut self, value: impl Into<Data>) -> &mut Self {
+        self.global(0x1, value.into())
+    }
+
+    pub(crate) fn logical_maximum(&mut self, value: impl Into<Data>) -> &mut Self {
+        self.global(0x2, value.into())
+    }
+
+    pub(crate) fn physical_minimum(&mut self, value: impl Into<Data>) -> &mut Self {
+        self.global(0x3, value.into())
+    }
+
+    pub(crate) fn physical_maximum(&mut self, value: impl Into<Data>) -> &mut Self {
+        self.global(0x4, value.into())
+    }
+
+    pub(crate) fn logical_range(&mut self, min: i32, max: i32) -> &mut Self {
+        self.logical_minimum(min).logical_maximum(max)
+    }
+
+    pub(crate) fn unit_exponent(&mut self, exponent: i8) -> &mut Self {
+        // Stored as a 4-bit two's complement number
+        self.global(0x5, Data::Unsigned((exponent as u8 & 0xf).into()))
+    }
+
+    pub(crate) fn unit(&mut self, unit: u32) -> &mut Self {
+        self.global(0x6, Data::Unsigned(unit))
+    }
+
+    pub(crate) fn report_size(&mut self, bits: u32) -> &mut Self {
+        self.global(0x7, Data::Unsigned(bits))
+    }
+
+    pub(crate) fn report_id(&mut self, id: u8) -> &mut Self {
+        self.global(0x8, Data::Unsigned(id.into()))
+    }
+
+    pub(crate) fn report_count(&mut self, count: u32) -> &mut Self {
+        self.global(0x9, Data::Unsigned(count))
+    }
+
+    /// Emit Push, items added by `f`, then Pop
+    pub(crate) fn push(&mut self, f: impl FnOnce(&mut Self)) -> &mut Self {
+        self.item(ItemType::Global, 0xa, None);
+        f(self);
+        self.item(ItemType::Global, 0xb, None)
+    }
+
+    pub(crate) fn usage(&mut self, usage: u16) -> &mut Self {
+        self.local(0x0, Data::Unsigned(usage.into()))
+    }
+
+    pub(crate) fn usage_minimum(&mut self, usage: u16) -> &mut Self {
+        self.local(0x1, Data::Unsigned(usage.into()))
+    }
+
+    pub(crate) fn usage_maximum(&mut self, usage: u16) -> &mut Self {
+        self.local(0x2, Data::Unsigned(usage.into()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_item_size() {
+        let bytes = Builder::new()
+            .logical_range(-1, 1)
+            .logical_range(0, 255)
+            .logical_range(-60, 50800)
+            .logical_minimum(Data::U32(0))
+            .logical_maximum(Data::U16(8191))
+            .usage(consumer::AC_PAN)
+            .unit_exponent(-3)
+            .push(|d| {
+                d.report_size(24);
+            })
+            .collection(Collection::Application, |_| ())
+            .finish();
+
+        assert_eq!(
+            hex::encode(bytes),
+            concat!(
+                "15ff2501",
+                "1500",
+                "26ff00",
+                "15c4",
+                "2770c60000",
+                "1700000000",
+                "26ff1f",
+                "0a3802",
+                "550d",
+                "a47518b4",
+                "a101c0",
+            )
+        );
+    }
+}
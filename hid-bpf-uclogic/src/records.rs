@@ -0,0 +1,217 @@
+//! Records for `--format json`
+//!
+//! The same information as the text output, for scripts. HID ids are
+//! formatted as in the text output and on the command line, like `"004C"`.
+
+use std::{fmt::Display, io, path::PathBuf};
+
+use eyre::{Result, bail};
+use serde::Serialize;
+
+use crate::{
+    config::DeviceConfig,
+    descriptor::{ParsedDeviceInfo, Protocol},
+    devices::{DeviceEntry, Features},
+    hid::Bus,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Format {
+    Text,
+    Json,
+}
+
+/// A USB device with HID interfaces, or a HID device on another bus
+#[derive(Debug, Serialize)]
+pub(crate) struct Device {
+    pub syspath: PathBuf,
+    pub bus: Option<Bus>,
+    pub vid: Option<String>,
+    pub pid: Option<String>,
+    pub manufacturer: Option<String>,
+    pub product: Option<String>,
+    /// Names of device database entries with the same USB id
+    pub candidates: Vec<String>,
+    pub interfaces: Vec<Interface>,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct Interface {
+    pub number: u8,
+    pub hid_id: String,
+    pub syspath: PathBuf,
+    /// The first interface, which the driver is loaded for
+    pub vendor: bool,
+    pub kind: InterfaceKind,
+    /// Name of the HID driver bound to the interface
+    pub driver: Option<String>,
+    /// Pinned driver of a vendor interface
+    pub pin: Option<String>,
+    pub hidraw: Vec<PathBuf>,
+    /// Event devices of the input devices created by the HID driver
+    pub input: Vec<PathBuf>,
+}
+
+/// What an interface is, from its current report descriptor
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum InterfaceKind {
+    /// Vendor-defined page 0xff00, as on v2 devices
+    Vendor,
+    /// Digitizer, as on v1 devices
+    Digitizer,
+    Keyboard,
+    Mouse,
+    /// Already has a descriptor generated by hid-bpf-uclogic
+    FixedUp,
+    Other,
+    /// The descriptor could not be read or decoded
+    Unknown,
+}
+
+/// The device database entry a device matched
+#[derive(Debug, Serialize)]
+pub(crate) struct Match {
+    pub name: String,
+    pub features: Features,
+}
+
+/// A device the driver is loaded for, or would be with `--dry-run`
+#[derive(Debug, Serialize)]
+pub(crate) struct Load {
+    pub device: Device,
+    pub hid_id: String,
+    pub firmware: String,
+    pub protocol: Protocol,
+    #[serde(rename = "match")]
+    pub matched: Option<Match>,
+    pub params: ParsedDeviceInfo,
+    /// From the config files, also applied to `params`
+    pub settings: DeviceConfig,
+    pub descriptor_size: usize,
+    /// Not pinned with `--wait`
+    pub pin: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub plan: Option<Plan>,
+}
+
+/// What `--dry-run` found
+#[derive(Debug, Serialize)]
+pub(crate) struct Plan {
+    pub checks: Vec<Check>,
+    pub steps: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct Check {
+    pub check: String,
+    /// Why the check failed, `null` if it passed
+    pub error: Option<String>,
+}
+
+/// A driver loaded in the kernel, as shown by `status`
+#[derive(Debug, Serialize)]
+pub(crate) struct Driver {
+    pub hid_id: String,
+    /// The HID device no longer exists
+    pub stale: bool,
+    /// Not pinned when held by `--wait`
+    pub pin: Option<String>,
+    pub link_id: Option<u32>,
+    pub struct_ops_map_id: Option<u32>,
+    /// Why the pinned link could not be inspected
+    pub link_error: Option<String>,
+    /// The USB device, if still plugged in
+    pub syspath: Option<PathBuf>,
+    /// Not known if the programs were not found
+    pub firmware: Option<String>,
+    pub protocol: Option<Protocol>,
+    #[serde(rename = "match")]
+    pub matched: Option<Match>,
+    pub params: Option<ParsedDeviceInfo>,
+    /// Why the device info could not be parsed
+    pub params_error: Option<String>,
+    pub prog_ids: Vec<u32>,
+    pub descriptor_size: Option<u32>,
+}
+
+impl Format {
+    pub(crate) fn parse(s: &str) -> Result<Self> {
+        match s {
+            "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            _ => bail!("Unknown output format {s:?}"),
+        }
+    }
+}
+
+impl Display for InterfaceKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            InterfaceKind::Vendor => write!(f, "vendor (0xff00)"),
+            InterfaceKind::Digitizer => write!(f, "digitizer"),
+            InterfaceKind::Keyboard => write!(f, "keyboard"),
+            InterfaceKind::Mouse => write!(f, "mouse"),
+            InterfaceKind::FixedUp => write!(f, "fixed up"),
+            InterfaceKind::Other => write!(f, "other"),
+            InterfaceKind::Unknown => write!(f, "unknown"),
+        }
+    }
+}
+
+impl Match {
+    pub(crate) fn new(entry: &DeviceEntry) -> Self {
+        Self {
+            name: entry.name.clone(),
+            features: entry.features.clone(),
+        }
+    }
+}
+
+pub(crate) fn hid_id(hid_id: i32) -> String {
+    format!("{hid_id:04X}")
+}
+
+/// Write a record as pretty-printed JSON, followed by a newline
+pub(crate) fn write(out: &mut impl io::Write, record: &impl Serialize) -> Result<()> {
+    serde_json::to_writer_pretty(&mut *out, record)?;
+    writeln!(out)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{descriptor::DeviceInfo, devices::DeviceDb};
+
+    #[test]
+    fn test_driver() {
+        let info = include_str!("../fixtures/device-info/gaomon-m7.txt");
+        let info = DeviceInfo::from_str(info).unwrap();
+        let db = DeviceDb::load_from(&[]).unwrap();
+        let entry = db.by_firmware(None, &info.firmware).unwrap();
+        let mut parsed = info.parse().unwrap();
+        parsed.apply_features(&entry.features);
+
+        let driver = Driver {
+            hid_id: hid_id(0x4c),
+            stale: false,
+            pin: Some("/sys/fs/bpf/hid-bpf-uclogic-004C".to_owned()),
+            link_id: Some(12),
+            struct_ops_map_id: Some(34),
+            link_error: None,
+            syspath: Some("/sys/devices/pci0000:00/0000:00:14.0/usb1/1-4".into()),
+            firmware: Some(info.firmware.clone()),
+            protocol: Some(info.protocol),
+            matched: Some(Match::new(entry)),
+            params: Some(parsed),
+            params_error: None,
+            prog_ids: vec![56, 57, 58],
+            descriptor_size: Some(292),
+        };
+
+        let mut out = Vec::new();
+        write(&mut out, &driver).unwrap();
+        insta::assert_snapshot!(String::from_utf8(out).unwrap());
+    }
+}
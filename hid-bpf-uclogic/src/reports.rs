@@ -0,0 +1,297 @@
+//! Layout of the reports sent by the BPF program
+//!
+//! This mirrors `union report` in `bpf/uclogic.bpf.c`. Generated descriptors
+//! are checked against it, so keep the two in sync.
+
+use eyre::{Result, bail};
+
+use crate::{
+    decode::{self, Field, ReportKind},
+    rdesc::{consumer, desktop, digitizers, flags, page},
+};
+
+pub(crate) const VENDOR_REPORT_ID: u8 = 0x08;
+pub(crate) const PAD_REPORT_ID: u8 = 0x03;
+pub(crate) const DIAL_REPORT_ID: u8 = 0xf0;
+
+/// `REPORT_SIZE`, in bytes
+pub(crate) const REPORT_SIZE: u32 = 12;
+
+/// `REPORT_NUM_BTN_BITS`, the size of `pad.btns`
+pub(crate) const PAD_BUTTON_BITS: u32 = 64;
+
+/// One of the structs in `union report`
+#[derive(Debug)]
+pub(crate) struct ReportLayout {
+    pub name: &'static str,
+    pub id: u8,
+    pub fields: &'static [FieldLayout],
+}
+
+/// A member of a report struct, which the descriptor may expose
+#[derive(Debug)]
+pub(crate) struct FieldLayout {
+    pub name: &'static str,
+    /// Offset in bits, counting the report ID byte
+    pub offset: u32,
+    pub size: u32,
+    pub usage: Usage,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Usage {
+    /// One field of exactly this usage, offset and size
+    Exact(u32),
+    /// Any number of fields of this usage page within the member
+    Page(u16),
+}
+
+const fn usage(page: u16, id: u16) -> Usage {
+    Usage::Exact((page as u32) << 16 | id as u32)
+}
+
+const fn field(name: &'static str, offset: u32, size: u32, usage: Usage) -> FieldLayout {
+    FieldLayout {
+        name,
+        offset,
+        size,
+        usage,
+    }
+}
+
+pub(crate) static REPORTS: &[ReportLayout] = &[
+    ReportLayout {
+        name: "stylus",
+        id: VENDOR_REPORT_ID,
+        fields: &[
+            field(
+                "flags.tip_switch",
+                8,
+                1,
+                usage(page::DIGITIZERS, digitizers::TIP_SWITCH),
+            ),
+            field(
+                "flags.barrel_switch",
+                9,
+                1,
+                usage(page::DIGITIZERS, digitizers::BARREL_SWITCH),
+            ),
+            field(
+                "flags.secondary_barrel_switch",
+                10,
+                1,
+                usage(page::DIGITIZERS, digitizers::SECONDARY_BARREL_SWITCH),
+            ),
+            field(
+                "flags.in_range",
+                15,
+                1,
+                usage(page::DIGITIZERS, digitizers::IN_RANGE),
+            ),
+            field("x", 16, 24, usage(page::GENERIC_DESKTOP, desktop::X)),
+            field("y", 40, 24, usage(page::GENERIC_DESKTOP, desktop::Y)),
+            field(
+                "pressure",
+                64,
+                16,
+                usage(page::DIGITIZERS, digitizers::TIP_PRESSURE),
+            ),
+            field("x_tilt", 80, 8, usage(page::DIGITIZERS, digitizers::X_TILT)),
+            field("y_tilt", 88, 8, usage(page::DIGITIZERS, digitizers::Y_TILT)),
+        ],
+    },
+    ReportLayout {
+        name: "pad",
+        id: PAD_REPORT_ID,
+        fields: &[
+            // Only the lowest bit, always zero
+            field(
+                "btn_stylus",
+                8,
+                1,
+                usage(page::DIGITIZERS, digitizers::BARREL_SWITCH),
+            ),
+            field("x", 16, 8, usage(page::GENERIC_DESKTOP, desktop::X)),
+            field("y", 24, 8, usage(page::GENERIC_DESKTOP, desktop::Y)),
+            field("btns", 32, PAD_BUTTON_BITS, Usage::Page(page::BUTTON)),
+        ],
+    },
+    ReportLayout {
+        name: "dial",
+        id: DIAL_REPORT_ID,
+        fields: &[
+            // Only the lowest bit, always zero
+            field("btn_stylus", 8, 1, usage(page::BUTTON, 1)),
+            field("x", 16, 8, usage(page::GENERIC_DESKTOP, desktop::X)),
+            field("y", 24, 8, usage(page::GENERIC_DESKTOP, desktop::Y)),
+            field(
+                "delta_1",
+                40,
+                8,
+                usage(page::GENERIC_DESKTOP, desktop::WHEEL),
+            ),
+            field("delta_2", 48, 8, usage(page::CONSUMER, consumer::AC_PAN)),
+        ],
+    },
+];
+
+impl FieldLayout {
+    fn contains(&self, field: &Field) -> bool {
+        let end = field.offset + field.size * field.count;
+        field.offset >= self.offset && end <= self.offset + self.size
+    }
+
+    /// Whether a descriptor field is this member, or fits in it
+    fn matches(&self, field: &Field) -> Result<bool> {
+        let Some(&first) = field.usages.first() else {
+            return Ok(false);
+        };
+        match self.usage {
+            Usage::Exact(usage) if field.usages == [usage] => {
+                if (field.offset, field.size * field.count) != (self.offset, self.size) {
+                    bail!(
+                        "{} is at bit {} with {} bits, but {} is at bit {} with {} bits",
+                        decode::usage_name(usage),
+                        field.offset,
+                        field.size * field.count,
+                        self.name,
+                        self.offset,
+                        self.size,
+                    );
+                }
+                Ok(true)
+            }
+            Usage::Exact(_) => Ok(false),
+            Usage::Page(page) => {
+                if field.usages.iter().any(|u| (u >> 16) as u16 != page) {
+                    return Ok(false);
+                }
+                if !self.contains(field) {
+                    bail!(
+                        "{} at bit {} does not fit in {} at bits {}..{}",
+                        decode::usage_name(first),
+                        field.offset,
+                        self.name,
+                        self.offset,
+                        self.offset + self.size,
+                    );
+                }
+                Ok(true)
+            }
+        }
+    }
+}
+
+/// Check that a descriptor only describes reports the BPF program sends,
+/// with every field where the program puts it
+pub(crate) fn check(desc: &[u8]) -> Result<()> {
+    for report in decode::layout(desc)? {
+        let Some(expected) = REPORTS.iter().find(|r| r.id == report.id) else {
+            bail!("Report ID {} is not sent by the BPF program", report.id);
+        };
+        if report.kind != ReportKind::Input {
+            bail!(
+                "{} report {} is not an input report",
+                report.kind,
+                report.id
+            );
+        }
+        if report.bits != REPORT_SIZE * 8 {
+            bail!(
+                "Report {} ({}) has {} bits, expected {}",
+                report.id,
+                expected.name,
+                report.bits,
+                REPORT_SIZE * 8
+            );
+        }
+
+        let data = report
+            .fields
+            .iter()
+            .filter(|f| f.flags & flags::CONSTANT as u32 == 0 && !f.usages.is_empty());
+        for field in data {
+            let mut found = false;
+            for member in expected.fields {
+                found |= member.matches(field).map_err(|e| {
+                    e.wrap_err(format!("In report {} ({})", report.id, expected.name))
+                })?;
+            }
+            if !found {
+                bail!(
+                    "Report {} ({}) has {} at bit {}, which is not in union report",
+                    report.id,
+                    expected.name,
+                    decode::usage_name(field.usages[0]),
+                    field.offset,
+                );
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rdesc::{Builder, Collection, flags::*};
+
+    fn pad_report(skip_bytes: u32, num_btns: u32, padding: u32) -> Vec<u8> {
+        Builder::new()
+            .usage_page(page::GENERIC_DESKTOP)
+            .usage(desktop::KEYPAD)
+            .collection(Collection::Application, |d| {
+                d.report_id(PAD_REPORT_ID)
+                    .report_size(8)
+                    .report_count(skip_bytes)
+                    .input(CONSTANT)
+                    .usage_page(page::BUTTON)
+                    .usage_minimum(1)
+                    .usage_maximum(num_btns as u16)
+                    .logical_range(0, 1)
+                    .report_size(1)
+                    .report_count(num_btns)
+                    .input(VARIABLE)
+                    .report_count(padding)
+                    .input(CONSTANT);
+            })
+            .finish()
+    }
+
+    #[test]
+    fn test_check() {
+        check(&pad_report(3, 12, 52)).unwrap();
+
+        // Buttons before pad.btns
+        let err = check(&pad_report(2, 64, 8)).unwrap_err();
+        assert!(format!("{err:#}").contains("does not fit in btns"));
+
+        // Report too short
+        let err = check(&pad_report(3, 12, 4)).unwrap_err();
+        assert!(format!("{err:#}").contains("has 48 bits, expected 96"));
+    }
+
+    #[test]
+    fn test_check_misplaced() {
+        let desc = Builder::new()
+            .usage_page(page::GENERIC_DESKTOP)
+            .usage(desktop::KEYPAD)
+            .collection(Collection::Application, |d| {
+                d.report_id(DIAL_REPORT_ID)
+                    .report_size(8)
+                    .report_count(3)
+                    .input(CONSTANT)
+                    .usage(desktop::WHEEL)
+                    .logical_range(-1, 1)
+                    .report_count(1)
+                    .input(VARIABLE | RELATIVE)
+                    .report_count(7)
+                    .input(CONSTANT);
+            })
+            .finish();
+
+        let err = check(&desc).unwrap_err();
+        assert!(format!("{err:#}").contains("but delta_1 is at bit 40"));
+    }
+}
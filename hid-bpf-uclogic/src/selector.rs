@@ -0,0 +1,208 @@
+//! Which USB device `--device` means
+//!
+//! Besides a syspath, a device can be given by USB id, by the HID id or
+//! hidraw node of one of its interfaces, or as the only supported device.
+
+use std::{
+    ffi::{OsStr, OsString},
+    path::{Path, PathBuf},
+};
+
+use eyre::{OptionExt, Result, bail, eyre};
+
+use crate::{
+    devices::{DeviceDb, UsbId},
+    sysfs::SysfsPath,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Selector {
+    /// A sysfs path of the USB device
+    Path(PathBuf),
+    /// `vvvv:pppp`
+    Usb(UsbId),
+    /// `hid:IIII`, any interface of the device
+    Hid(i32),
+    /// `/dev/hidrawN`, any interface of the device
+    Hidraw(OsString),
+    /// `auto`, the only supported device plugged in
+    Auto,
+}
+
+impl Selector {
+    pub(crate) fn parse(s: &OsStr) -> Result<Self> {
+        let path = Path::new(s);
+        if let Ok(name) = path.strip_prefix("/dev") {
+            if !name.to_string_lossy().starts_with("hidraw") {
+                bail!("Only hidraw devices can be selected in /dev");
+            }
+            return Ok(Self::Hidraw(name.as_os_str().to_owned()));
+        }
+        if path.components().count() > 1 {
+            return Ok(Self::Path(path.to_owned()));
+        }
+
+        let Some(s) = s.to_str() else {
+            return Ok(Self::Path(path.to_owned()));
+        };
+        if s == "auto" {
+            return Ok(Self::Auto);
+        }
+        if let Some(id) = s.strip_prefix("hid:") {
+            let id = i32::from_str_radix(id, 16).map_err(|_| eyre!("Invalid HID id {id:?}"))?;
+            return Ok(Self::Hid(id));
+        }
+        if s.contains(':') {
+            return UsbId::try_from(s.to_owned())
+                .map(Self::Usb)
+                .map_err(|e| eyre!(e));
+        }
+        Ok(Self::Path(path.to_owned()))
+    }
+}
+
+/// Find the USB device, which has to be the only match
+pub(crate) fn resolve<'a>(
+    sysfs: &SysfsPath<'a>,
+    db: &DeviceDb,
+    selector: &Selector,
+) -> Result<SysfsPath<'a>> {
+    let usb_hid = crate::find_hid_devices(sysfs)?;
+
+    // The HID device or hidraw node to start from
+    let child = match selector {
+        Selector::Path(path) => {
+            return sysfs
+                .sub(path)?
+                .ok_or_else(|| eyre!("Device {} not found", path.display()));
+        }
+        Selector::Hid(id) => {
+            let hid_bus = sysfs
+                .get_subsystem("hid")?
+                .ok_or_eyre("No hid subsystem found in sysfs")?;
+            let mut found = None;
+            for dev in hid_bus.devices()? {
+                let (name, dev) = dev?;
+                if crate::parse_hid_id(&name) == Some(*id) {
+                    found = Some(dev);
+                    break;
+                }
+            }
+            Some(found.ok_or_else(|| eyre!("HID device {id:04X} not found"))?)
+        }
+        Selector::Hidraw(name) => {
+            let hidraw = sysfs.get_subsystem("hidraw")?;
+            let dev = hidraw
+                .map(|h| h.sub(name.as_os_str()))
+                .transpose()?
+                .flatten();
+            Some(dev.ok_or_else(|| eyre!("{} not found", Path::new(name).display()))?)
+        }
+        Selector::Usb(_) | Selector::Auto => None,
+    };
+
+    let mut matches = Vec::new();
+    if let Some(child) = child {
+        let usb = child.find_map_parent(|p| Ok(usb_hid.contains_key(&p).then_some(p)))?;
+        matches.extend(usb);
+    } else {
+        for usb in usb_hid.into_keys() {
+            let selected = match selector {
+                Selector::Usb(id) => crate::device_id(&usb)?.map(|h| h.id) == Some(*id),
+                _ => crate::device_supported(&usb, db)?,
+            };
+            if selected {
+                matches.push(usb);
+            }
+        }
+    }
+
+    match matches.len() {
+        0 => match selector {
+            Selector::Usb(id) => bail!("No USB HID device {id} found"),
+            Selector::Auto => bail!("No supported device found"),
+            _ => bail!("Device is not a USB HID device"),
+        },
+        1 => Ok(matches.pop().unwrap()),
+        _ => {
+            let mut paths = matches
+                .iter()
+                .map(|m| Ok(m.recover_path()?.display().to_string()))
+                .collect::<Result<Vec<_>>>()?;
+            paths.sort();
+            bail!(
+                "{} devices match, select one by syspath:\n  {}",
+                paths.len(),
+                paths.join("\n  ")
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixture::{FakeSysfs, UsbDevice};
+
+    fn parse(s: &str) -> Selector {
+        Selector::parse(OsStr::new(s)).unwrap()
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            parse("/sys/bus/usb/devices/1-4"),
+            Selector::Path("/sys/bus/usb/devices/1-4".into())
+        );
+        assert_eq!(
+            parse("256c:0064"),
+            Selector::Usb(UsbId {
+                vid: 0x256c,
+                pid: 0x0064
+            })
+        );
+        assert_eq!(parse("hid:004C"), Selector::Hid(0x4c));
+        assert_eq!(parse("/dev/hidraw3"), Selector::Hidraw("hidraw3".into()));
+        assert_eq!(parse("auto"), Selector::Auto);
+
+        assert!(Selector::parse(OsStr::new("/dev/input/event3")).is_err());
+        assert!(Selector::parse(OsStr::new("hid:xyz")).is_err());
+        assert!(Selector::parse(OsStr::new("256c:64")).is_err());
+    }
+
+    #[test]
+    fn test_resolve() {
+        let fake = FakeSysfs::new("selector");
+        fake.usb("1-4", UsbDevice::GAOMON_M7);
+        fake.usb("1-5", UsbDevice::HUION_HC16);
+        fake.usb("1-6", UsbDevice::RECEIVER);
+
+        let sysfs = fake.sysfs();
+        let sysfs = sysfs.root().unwrap();
+        let db = DeviceDb::load_from(&[]).unwrap();
+
+        let resolve =
+            |s: &str| resolve(&sysfs, &db, &parse(s)).map(|usb| usb.recover_path().unwrap());
+        let err = |s: &str| format!("{:#}", resolve(s).unwrap_err());
+
+        assert_eq!(resolve("hid:004D").unwrap(), fake.path("1-4"));
+        assert_eq!(resolve("/dev/hidraw3").unwrap(), fake.path("1-5"));
+        assert_eq!(resolve("046d:c52b").unwrap(), fake.path("1-6"));
+        assert_eq!(
+            resolve("devices/pci0000:00/0000:00:14.0/usb1/1-5").unwrap(),
+            fake.path("1-5")
+        );
+
+        // The M7 and HC16 share a USB id
+        let ambiguous = format!(
+            "2 devices match, select one by syspath:\n  {}\n  {}",
+            fake.path("1-4").display(),
+            fake.path("1-5").display()
+        );
+        assert_eq!(err("256c:0064"), ambiguous);
+        assert_eq!(err("auto"), ambiguous);
+
+        assert_eq!(err("hid:0099"), "HID device 0099 not found");
+        assert_eq!(err("256c:0060"), "No USB HID device 256c:0060 found");
+    }
+}
@@ -2,7 +2,7 @@ use std::{
     ffi::{CString, OsString},
     io::{self, Read, Write},
     os::fd::{AsRawFd, FromRawFd},
-    path::PathBuf,
+    path::{Path, PathBuf},
     str::FromStr,
 };
 
@@ -14,14 +14,15 @@ use openat::Dir;
 pub(crate) struct Sysfs {
     dir: Dir,
     dev: libc::dev_t,
+    ino: libc::ino_t,
 }
 
 impl Sysfs {
     pub(crate) fn get() -> io::Result<Self> {
         use std::mem::MaybeUninit;
 
-        let dir = Dir::open("/sys")?;
-        let fd = dir.as_raw_fd();
+        let sysfs = Self::open(Path::new("/sys"))?;
+        let fd = sysfs.dir.as_raw_fd();
 
         let sfs = unsafe {
             let mut sfs = MaybeUninit::zeroed();
@@ -39,9 +40,19 @@ impl Sysfs {
             ));
         }
 
-        let dev = dir.self_metadata()?.stat().st_dev;
+        Ok(sysfs)
+    }
+
+    /// Use a directory laid out like sysfs, without checking that it is one
+    pub(crate) fn open(path: &Path) -> io::Result<Self> {
+        let dir = Dir::open(path)?;
+        let stat = *dir.self_metadata()?.stat();
 
-        Ok(Self { dir, dev })
+        Ok(Self {
+            dir,
+            dev: stat.st_dev,
+            ino: stat.st_ino,
+        })
     }
 }
 
@@ -69,11 +80,10 @@ impl std::hash::Hash for SysfsPath<'_> {
 impl Sysfs {
     pub(crate) fn root(&self) -> io::Result<SysfsPath> {
         let dir = self.dir.try_clone()?;
-        let ino = self.dir.self_metadata()?.stat().st_ino;
         Ok(SysfsPath {
             sysfs: self,
             dir,
-            ino,
+            ino: self.ino,
         })
     }
 }
@@ -192,6 +202,18 @@ impl SysfsPath<'_> {
         Ok(iter.filter_map(move |r| r.and_then(entry).transpose()))
     }
 
+    /// Names in a directory, sorted, or empty if there is no such directory
+    pub(crate) fn list(&self, path: &str) -> io::Result<Vec<OsString>> {
+        let Some(iter) = map_not_found(self.dir.list_dir(path))? else {
+            return Ok(Vec::new());
+        };
+        let mut names = iter
+            .map(|ent| Ok(ent?.file_name().to_os_string()))
+            .collect::<io::Result<Vec<_>>>()?;
+        names.sort();
+        Ok(names)
+    }
+
     pub(crate) fn property_trim(&self, name: &str) -> io::Result<Option<String>> {
         Ok(self.property(name)?.map(|s| s.trim().to_owned()))
     }
@@ -205,10 +227,16 @@ impl SysfsPath<'_> {
         loop {
             let next = dir.sub("..");
 
+            let at_root = dir.ino == dir.sysfs.ino;
+
             if let Some(res) = f(dir)? {
                 return Ok(Some(res));
             }
 
+            if at_root {
+                return Ok(None);
+            }
+
             dir = if let Some(parent) = next? {
                 parent
             } else {
@@ -0,0 +1,129 @@
+//! Udev rules and systemd units that load the driver when a device is added
+//!
+//! The rules match supported devices by USB id, have huion-switcher read the
+//! firmware ID, then start `hid-bpf-uclogic@<usb device>.service` if the
+//! firmware ID is supported as well. The service takes the device info from
+//! the udev database, so the device is only queried once.
+
+use std::{
+    fmt::Write,
+    path::{Path, PathBuf},
+};
+
+use eyre::{Result, bail};
+
+use crate::devices::{DeviceDb, UsbId};
+
+pub(crate) const RULES_PATH: &str = "udev/rules.d/70-hid-bpf-uclogic.rules";
+pub(crate) const UNIT_PATH: &str = "systemd/system/hid-bpf-uclogic@.service";
+
+/// Where generated files point to
+#[derive(Debug)]
+pub(crate) struct Paths {
+    /// The `hid-bpf-uclogic` executable
+    pub bin: PathBuf,
+    /// huion-switcher, relative paths are looked up by udev
+    pub huion_switcher: PathBuf,
+}
+
+/// Files to install, relative to a prefix like `/etc` or `/usr/lib`
+pub(crate) fn files(db: &DeviceDb, paths: &Paths) -> Result<Vec<(&'static str, String)>> {
+    Ok(vec![
+        (RULES_PATH, rules(db, &paths.huion_switcher)?),
+        (UNIT_PATH, unit(&paths.bin)),
+    ])
+}
+
+fn rules(db: &DeviceDb, huion_switcher: &Path) -> Result<String> {
+    let mut usb_ids: Vec<UsbId> = db.entries().flat_map(|e| e.usb.iter().copied()).collect();
+    usb_ids.sort_by_key(|id| (id.vid, id.pid));
+    usb_ids.dedup();
+
+    let mut out = String::from(
+        r#"# Generated by hid-bpf-uclogic install-udev
+
+# A USB device is bound after its interfaces are probed, so its HID devices
+# all exist by then, unlike on add
+ACTION!="bind", GOTO="hid_bpf_uclogic_end"
+SUBSYSTEM!="usb", GOTO="hid_bpf_uclogic_end"
+ENV{DEVTYPE}!="usb_device", GOTO="hid_bpf_uclogic_end"
+
+# Sets HUION_FIRMWARE_ID
+"#,
+    );
+
+    for id in usb_ids {
+        writeln!(
+            out,
+            r#"{}, IMPORT{{program}}="{} %S%p""#,
+            match_usb(id),
+            huion_switcher.display()
+        )?;
+    }
+
+    for entry in db.entries() {
+        for pattern in &entry.firmware {
+            if pattern.contains(['|', '?', '[', ']', '"', '\\']) {
+                bail!(
+                    "Firmware ID {pattern:?} of {} cannot be matched by udev",
+                    entry.name
+                );
+            }
+        }
+
+        writeln!(out, "\n# {}", entry.name)?;
+        for &id in &entry.usb {
+            writeln!(
+                out,
+                r#"{}, ENV{{HUION_FIRMWARE_ID}}=="{}", TAG+="systemd", ENV{{SYSTEMD_WANTS}}+="hid-bpf-uclogic@%k.service""#,
+                match_usb(id),
+                entry.firmware.join("|"),
+            )?;
+        }
+    }
+
+    writeln!(out, "\nLABEL=\"hid_bpf_uclogic_end\"")?;
+    Ok(out)
+}
+
+fn match_usb(id: UsbId) -> String {
+    format!(
+        r#"ATTR{{idVendor}}=="{:04x}", ATTR{{idProduct}}=="{:04x}""#,
+        id.vid, id.pid
+    )
+}
+
+fn unit(bin: &Path) -> String {
+    format!(
+        "\
+# Generated by hid-bpf-uclogic install-udev
+# Started by 70-hid-bpf-uclogic.rules, the instance is the USB device name
+
+[Unit]
+Description=hid-bpf-uclogic driver for USB device %i
+
+[Service]
+Type=oneshot
+ExecStart={} --device /sys/bus/usb/devices/%i --from-udev-db --quiet
+",
+        bin.display()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_files() {
+        let db = DeviceDb::load_from(&[]).unwrap();
+        let paths = Paths {
+            bin: "/usr/bin/hid-bpf-uclogic".into(),
+            huion_switcher: "huion-switcher".into(),
+        };
+
+        for (path, text) in files(&db, &paths).unwrap() {
+            insta::assert_snapshot!(path.rsplit('/').next().unwrap(), text);
+        }
+    }
+}
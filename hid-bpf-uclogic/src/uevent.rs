@@ -0,0 +1,188 @@
+//! Device events, as sent by the kernel to udev
+
+use std::{
+    collections::HashMap,
+    io,
+    os::fd::{AsRawFd, FromRawFd, OwnedFd},
+};
+
+use eyre::Result;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Uevent {
+    pub action: String,
+    /// Path of the device relative to the sysfs root, like `/devices/...`
+    pub devpath: String,
+    pub subsystem: String,
+    pub devtype: Option<String>,
+}
+
+/// What an event source has next
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Received {
+    Event(Uevent),
+    /// Events were dropped, so any device may have come or gone
+    Overflow,
+}
+
+/// Where events come from, so that tests can replace the kernel
+pub(crate) trait EventSource {
+    /// Wait for the next event, or `None` if there will be no more
+    fn next_event(&mut self) -> Result<Option<Received>>;
+}
+
+/// Kernel uevents from a netlink socket
+///
+/// These are the events udev itself receives, so nothing depends on udev
+/// running or having processed them.
+#[derive(Debug)]
+pub(crate) struct Netlink {
+    fd: OwnedFd,
+}
+
+impl Uevent {
+    /// Parse `KEY=value` fields, ignoring the `action@devpath` header the
+    /// kernel sends first
+    pub(crate) fn parse<'a>(fields: impl IntoIterator<Item = &'a str>) -> Option<Self> {
+        let mut props: HashMap<&str, &str> = fields
+            .into_iter()
+            .filter_map(|f| f.split_once('='))
+            .collect();
+
+        Some(Self {
+            action: props.remove("ACTION")?.to_owned(),
+            devpath: props.remove("DEVPATH")?.to_owned(),
+            subsystem: props.remove("SUBSYSTEM")?.to_owned(),
+            devtype: props.remove("DEVTYPE").map(str::to_owned),
+        })
+    }
+
+    /// The last component of `devpath`, which is the device name
+    pub(crate) fn name(&self) -> &str {
+        self.devpath.rsplit('/').next().unwrap()
+    }
+}
+
+impl Netlink {
+    pub(crate) fn open() -> io::Result<Self> {
+        use libc::*;
+
+        // SAFETY: No pointers are involved
+        let fd = unsafe {
+            socket(
+                AF_NETLINK,
+                SOCK_DGRAM | SOCK_CLOEXEC,
+                NETLINK_KOBJECT_UEVENT,
+            )
+        };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        // SAFETY: fd is a new file descriptor owned by nothing else
+        let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+
+        // SAFETY: sockaddr_nl is plain data
+        let mut addr: sockaddr_nl = unsafe { std::mem::zeroed() };
+        addr.nl_family = AF_NETLINK as sa_family_t;
+        // Group 1 has kernel events, group 2 has udev's own
+        addr.nl_groups = 1;
+
+        // SAFETY: addr is a valid sockaddr_nl of the given size
+        let res = unsafe {
+            bind(
+                fd.as_raw_fd(),
+                &addr as *const sockaddr_nl as *const sockaddr,
+                size_of_val(&addr) as socklen_t,
+            )
+        };
+        if res < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(Self { fd })
+    }
+}
+
+impl EventSource for Netlink {
+    fn next_event(&mut self) -> Result<Option<Received>> {
+        use libc::*;
+
+        let mut buf = vec![0u8; 8192];
+
+        loop {
+            // SAFETY: sockaddr_nl is plain data
+            let mut addr: sockaddr_nl = unsafe { std::mem::zeroed() };
+            let mut addr_len = size_of_val(&addr) as socklen_t;
+
+            // SAFETY: buf and addr are valid for the given sizes
+            let len = unsafe {
+                recvfrom(
+                    self.fd.as_raw_fd(),
+                    buf.as_mut_ptr().cast(),
+                    buf.len(),
+                    0,
+                    &mut addr as *mut sockaddr_nl as *mut sockaddr,
+                    &mut addr_len,
+                )
+            };
+            if len < 0 {
+                let err = io::Error::last_os_error();
+                match err.raw_os_error() {
+                    Some(EINTR) => continue,
+                    // The socket buffer filled up during a burst of events
+                    Some(ENOBUFS) => return Ok(Some(Received::Overflow)),
+                    _ => return Err(err.into()),
+                }
+            }
+
+            // Only trust messages from the kernel
+            if addr.nl_pid != 0 {
+                continue;
+            }
+
+            let fields = buf[..len as usize]
+                .split(|&b| b == 0)
+                .filter_map(|f| std::str::from_utf8(f).ok());
+            if let Some(event) = Uevent::parse(fields) {
+                return Ok(Some(Received::Event(event)));
+            }
+        }
+    }
+}
+
+/// Recorded events, separated by blank lines, in the format of
+/// `udevadm monitor --kernel --property` without the header lines
+#[cfg(test)]
+pub(crate) fn parse_events(text: &str) -> Vec<Uevent> {
+    text.split("\n\n")
+        .filter_map(|block| Uevent::parse(block.lines().map(str::trim)))
+        .collect()
+}
+
+#[cfg(test)]
+impl EventSource for std::vec::IntoIter<Received> {
+    fn next_event(&mut self) -> Result<Option<Received>> {
+        Ok(self.next())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let msg = b"remove@/devices/pci0000:00/0000:00:14.0/usb1/1-4\0ACTION=remove\0DEVPATH=/devices/pci0000:00/0000:00:14.0/usb1/1-4\0SUBSYSTEM=usb\0DEVTYPE=usb_device\0SEQNUM=4242\0";
+        let fields = msg
+            .split(|&b| b == 0)
+            .map(|f| std::str::from_utf8(f).unwrap());
+        let event = Uevent::parse(fields).unwrap();
+
+        assert_eq!(event.action, "remove");
+        assert_eq!(event.subsystem, "usb");
+        assert_eq!(event.devtype.as_deref(), Some("usb_device"));
+        assert_eq!(event.name(), "1-4");
+
+        assert!(Uevent::parse(["ACTION=add", "SUBSYSTEM=usb"]).is_none());
+    }
+}
@@ -0,0 +1,245 @@
+use std::{
+    fs::File,
+    io,
+    os::fd::AsRawFd,
+    path::{Path, PathBuf},
+};
+
+use eyre::{Context, Result, bail};
+
+use crate::{
+    descriptor::{DeviceInfo, Protocol},
+    sysfs::SysfsPath,
+};
+
+const LANG_EN_US: u16 = 0x0409;
+
+const STRING_V1_PARAMS: u8 = 100;
+const STRING_V2_PARAMS: u8 = 200;
+const STRING_FIRMWARE: u8 = 201;
+
+/// Source of raw USB string descriptors
+pub(crate) trait StringDescriptors {
+    /// Read string descriptor `index` in language `lang`, including the
+    /// two-byte header
+    fn string_descriptor(&mut self, index: u8, lang: u16) -> io::Result<Vec<u8>>;
+}
+
+/// A USB device opened through usbfs
+#[derive(Debug)]
+pub(crate) struct Usbfs {
+    path: PathBuf,
+    file: File,
+}
+
+#[repr(C)]
+#[allow(non_camel_case_types)]
+struct usbdevfs_ctrltransfer {
+    b_request_type: u8,
+    b_request: u8,
+    w_value: u16,
+    w_index: u16,
+    w_length: u16,
+    timeout: u32,
+    data: *mut libc::c_void,
+}
+
+const fn ioc(dir: u32, nr: u32, size: usize) -> libc::Ioctl {
+    ((dir << 30) | ((size as u32) << 16) | ((b'U' as u32) << 8) | nr) as _
+}
+
+const USBDEVFS_CONTROL: libc::Ioctl = ioc(3, 0, size_of::<usbdevfs_ctrltransfer>());
+const USBDEVFS_RESET: libc::Ioctl = ioc(0, 20, 0);
+
+const USB_DIR_IN: u8 = 0x80;
+const USB_REQ_GET_DESCRIPTOR: u8 = 0x06;
+const USB_DT_STRING: u8 = 0x03;
+
+const CONTROL_TIMEOUT_MS: u32 = 1000;
+
+impl Usbfs {
+    /// Open the usbfs node of a USB device found in sysfs
+    pub(crate) fn open(device: &SysfsPath) -> Result<Self> {
+        let num = |name| -> Result<u32> {
+            device
+                .property_trim(name)?
+                .ok_or_else(|| eyre::eyre!("No {name} found for USB device"))?
+                .parse()
+                .wrap_err_with(|| format!("Invalid {name}"))
+        };
+        let path = PathBuf::from(format!(
+            "/dev/bus/usb/{:03}/{:03}",
+            num("busnum")?,
+            num("devnum")?
+        ));
+        let file = File::options()
+            .read(true)
+            .write(true)
+            .open(&path)
+            .wrap_err_with(|| format!("Opening {}", path.display()))?;
+        Ok(Self { path, file })
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Reset the device, which then enumerates again as if replugged
+    pub(crate) fn reset(&mut self) -> io::Result<()> {
+        // SAFETY: USBDEVFS_RESET takes no argument
+        let res = unsafe { libc::ioctl(self.file.as_raw_fd(), USBDEVFS_RESET) };
+        if res < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
+
+impl StringDescriptors for Usbfs {
+    fn string_descriptor(&mut self, index: u8, lang: u16) -> io::Result<Vec<u8>> {
+        let mut buf = vec![0u8; 255];
+        let mut ctrl = usbdevfs_ctrltransfer {
+            b_request_type: USB_DIR_IN,
+            b_request: USB_REQ_GET_DESCRIPTOR,
+            w_value: (USB_DT_STRING as u16) << 8 | index as u16,
+            w_index: lang,
+            w_length: buf.len() as u16,
+            timeout: CONTROL_TIMEOUT_MS,
+            data: buf.as_mut_ptr().cast(),
+        };
+
+        // SAFETY: ctrl is a valid usbdevfs_ctrltransfer, and data points to
+        // w_length writable bytes
+        let res = unsafe { libc::ioctl(self.file.as_raw_fd(), USBDEVFS_CONTROL, &mut ctrl) };
+        if res < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        buf.truncate(res as usize);
+        Ok(buf)
+    }
+}
+
+/// Check the header of a string descriptor and return the payload
+fn string_payload(desc: &[u8]) -> Result<&[u8]> {
+    if desc.len() < 2 || desc[1] != USB_DT_STRING {
+        bail!("Not a string descriptor");
+    }
+    if desc[0] as usize != desc.len() {
+        bail!("String descriptor has incorrect length");
+    }
+    Ok(&desc[2..])
+}
+
+fn read_firmware(dev: &mut impl StringDescriptors) -> Result<String> {
+    let desc = dev
+        .string_descriptor(STRING_FIRMWARE, LANG_EN_US)
+        .wrap_err("Reading firmware ID")?;
+    let payload = string_payload(&desc).wrap_err("Reading firmware ID")?;
+    let units: Vec<u16> = payload
+        .chunks_exact(2)
+        .map(|c| u16::from_le_bytes([c[0], c[1]]))
+        .collect();
+    String::from_utf16(&units).wrap_err("Firmware ID is not valid UTF-16")
+}
+
+/// Read the firmware ID and parameters of a tablet
+///
+/// Reading the parameters is also what switches the tablet into vendor mode,
+/// disabling its default functionality. v2 parameters are tried first,
+/// falling back to v1.
+pub(crate) fn read_device_info(dev: &mut impl StringDescriptors) -> Result<DeviceInfo> {
+    let firmware = read_firmware(dev)?;
+
+    let v2 = dev.string_descriptor(STRING_V2_PARAMS, LANG_EN_US);
+    let (protocol, magic_bytes) = match v2 {
+        Ok(desc) if string_payload(&desc).is_ok_and(|p| !p.is_empty()) => (Protocol::V2, desc),
+        _ => {
+            let desc = dev
+                .string_descriptor(STRING_V1_PARAMS, LANG_EN_US)
+                .wrap_err("Device responds to neither v2 nor v1 parameter requests")?;
+            string_payload(&desc).wrap_err("Reading v1 parameters")?;
+            (Protocol::V1, desc)
+        }
+    };
+
+    Ok(DeviceInfo {
+        firmware,
+        magic_bytes,
+        protocol,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    /// Replays string descriptors recorded from a device
+    struct Recorded(HashMap<u8, Vec<u8>>);
+
+    impl Recorded {
+        fn new(descs: &[(u8, &str)]) -> Self {
+            Self(
+                descs
+                    .iter()
+                    .map(|&(index, hex_str)| (index, hex::decode(hex_str).unwrap()))
+                    .collect(),
+            )
+        }
+    }
+
+    impl StringDescriptors for Recorded {
+        fn string_descriptor(&mut self, index: u8, lang: u16) -> io::Result<Vec<u8>> {
+            assert_eq!(lang, LANG_EN_US);
+            // Devices stall unknown string descriptor requests
+            self.0
+                .get(&index)
+                .cloned()
+                .ok_or_else(|| io::Error::from_raw_os_error(libc::EPIPE))
+        }
+    }
+
+    fn utf16_desc(s: &str) -> String {
+        let payload: Vec<u8> = s.encode_utf16().flat_map(u16::to_le_bytes).collect();
+        let mut desc = vec![payload.len() as u8 + 2, USB_DT_STRING];
+        desc.extend(payload);
+        hex::encode(desc)
+    }
+
+    #[test]
+    fn test_read_v2() {
+        let mut dev = Recorded::new(&[
+            (STRING_FIRMWARE, &utf16_desc("GM001_T207_210524")),
+            (STRING_V2_PARAMS, "1303e9c900048600ff1fd813030d1000043c3e"),
+        ]);
+
+        let info = read_device_info(&mut dev).unwrap();
+        assert_eq!(info.firmware, "GM001_T207_210524");
+        assert_eq!(info.protocol, Protocol::V2);
+        assert_eq!(
+            hex::encode(&info.magic_bytes),
+            "1303e9c900048600ff1fd813030d1000043c3e"
+        );
+    }
+
+    #[test]
+    fn test_read_v1() {
+        let mut dev = Recorded::new(&[
+            (STRING_FIRMWARE, &utf16_desc("HUION_M182")),
+            (STRING_V1_PARAMS, "0c03409ca8610300ff07a00f"),
+        ]);
+
+        let info = read_device_info(&mut dev).unwrap();
+        assert_eq!(info.firmware, "HUION_M182");
+        assert_eq!(info.protocol, Protocol::V1);
+        assert_eq!(info.parse().unwrap().x_max, 40000);
+    }
+
+    #[test]
+    fn test_read_unsupported() {
+        let mut dev = Recorded::new(&[(STRING_FIRMWARE, &utf16_desc("SOMETHING_ELSE"))]);
+        assert!(read_device_info(&mut dev).is_err());
+    }
+}
@@ -0,0 +1,128 @@
+//! Checking that the fixup took effect, after attaching
+//!
+//! Attaching succeeds as long as the kernel accepts the BPF program. The
+//! device is then probed again, and only sysfs shows whether that happened
+//! and which descriptor won, as another HID-BPF program may be attached too.
+
+use std::{
+    ffi::{OsStr, OsString},
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use eyre::{Result, bail};
+
+use crate::sysfs::SysfsPath;
+
+/// How long the kernel gets to probe the device again
+pub(crate) const TIMEOUT: Duration = Duration::from_secs(2);
+
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Wait for the HID device to have the new descriptor and input devices,
+/// returning the names of the input devices
+///
+/// On timeout, the error is why the last check failed.
+pub(crate) fn wait_fixed(
+    hid_bus: &SysfsPath,
+    hid_name: &OsStr,
+    orig_rdesc: &[u8],
+    new_rdesc: &[u8],
+    timeout: Duration,
+) -> Result<Vec<OsString>> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        match check_fixed(hid_bus, hid_name, orig_rdesc, new_rdesc) {
+            Ok(inputs) => return Ok(inputs),
+            Err(e) if Instant::now() >= deadline => return Err(e),
+            Err(_) => std::thread::sleep(POLL_INTERVAL),
+        }
+    }
+}
+
+fn check_fixed(
+    hid_bus: &SysfsPath,
+    hid_name: &OsStr,
+    orig_rdesc: &[u8],
+    new_rdesc: &[u8],
+) -> Result<Vec<OsString>> {
+    // Looked up again each time, in case the device is re-created
+    let name = hid_name.to_string_lossy();
+    let Some(hid) = hid_bus.sub(&Path::new("devices").join(hid_name))? else {
+        bail!("HID device {name} no longer exists");
+    };
+
+    let rdesc = hid.read("report_descriptor")?;
+    if rdesc != new_rdesc {
+        if rdesc == orig_rdesc {
+            bail!("HID device {name} still has its original descriptor (Was it probed again?)");
+        }
+        bail!(
+            "HID device {name} has a different {} byte descriptor (Is another HID-BPF program attached?)",
+            rdesc.len()
+        );
+    }
+
+    if hid.sub("driver")?.is_none() {
+        bail!("No driver is bound to HID device {name}");
+    }
+    let inputs: Vec<_> = hid
+        .list("input")?
+        .into_iter()
+        .filter(|n| n.to_string_lossy().starts_with("input"))
+        .collect();
+    if inputs.is_empty() {
+        bail!("No input devices were created for HID device {name}");
+    }
+    Ok(inputs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixture::{FakeSysfs, KEYBOARD_RDESC, UsbDevice, VENDOR_RDESC};
+
+    #[test]
+    fn test_wait_fixed() {
+        let fake = FakeSysfs::new("verify");
+        fake.usb("1-4", UsbDevice::GAOMON_M7);
+        let hid = fake.path("1-4").join("1-4:1.0/0003:256C:0064.004C");
+        let new_rdesc = &[0x05, 0x0d, 0x09, 0x02];
+
+        let check = || {
+            let sysfs = fake.sysfs();
+            let root = sysfs.root().unwrap();
+            let hid_bus = root.get_subsystem("hid").unwrap().unwrap();
+            let name = OsStr::new("0003:256C:0064.004C");
+            wait_fixed(&hid_bus, name, VENDOR_RDESC, new_rdesc, Duration::ZERO)
+                .map_err(|e| format!("{e:#}"))
+        };
+
+        assert_eq!(
+            check(),
+            Err("HID device 0003:256C:0064.004C still has its original descriptor (Was it probed again?)".to_owned())
+        );
+
+        std::fs::write(hid.join("report_descriptor"), KEYBOARD_RDESC).unwrap();
+        assert!(check().unwrap_err().contains("another HID-BPF program"));
+
+        std::fs::write(hid.join("report_descriptor"), new_rdesc).unwrap();
+        assert_eq!(
+            check(),
+            Err("No driver is bound to HID device 0003:256C:0064.004C".to_owned())
+        );
+
+        std::os::unix::fs::symlink(
+            fake.root.join("bus/hid/drivers/hid-generic"),
+            hid.join("driver"),
+        )
+        .unwrap();
+        assert_eq!(check(), Ok(vec![OsString::from("input0")]));
+
+        std::fs::remove_dir_all(hid.join("input")).unwrap();
+        assert_eq!(
+            check(),
+            Err("No input devices were created for HID device 0003:256C:0064.004C".to_owned())
+        );
+    }
+}
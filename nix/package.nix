@@ -1,11 +1,11 @@
 {
   lib,
+  stdenv,
   rustPlatform,
   zlib,
   elfutils,
   libbpf,
   pkg-config,
-  makeWrapper,
   buildPackages,
   huion-switcher,
 }:
@@ -25,7 +25,6 @@ rustPlatform.buildRustPackage {
 
   nativeBuildInputs = [
     pkg-config
-    makeWrapper
     buildPackages.llvmPackages.clang-unwrapped
   ];
 
@@ -37,9 +36,9 @@ rustPlatform.buildRustPackage {
 
   doCheck = true;
 
-  postInstall = ''
-    wrapProgram "$out/bin/"* \
-      --set PATH ${lib.makeBinPath [ huion-switcher ]}
+  postInstall = lib.optionalString (stdenv.buildPlatform.canExecute stdenv.hostPlatform) ''
+    $out/bin/hid-bpf-uclogic install-udev --prefix $out/lib \
+      --huion-switcher ${lib.getExe huion-switcher}
   '';
 
   meta.mainProgram = "hid-bpf-uclogic";